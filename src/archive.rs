@@ -0,0 +1,164 @@
+use crate::error::ProjectError;
+use crate::git_utils::ARCHIVE_SOURCE_CONFIG_KEY;
+use crate::logger::{log_to_file, log_trace, LogLevel};
+use crate::redact::{redact_text, redact_url};
+use flate2::read::GzDecoder;
+use indicatif::ProgressBar;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+/// Author and committer of the commit an archive import creates.
+const IMPORT_IDENTITY: [(&str, &str); 4] = [
+    ("GIT_AUTHOR_NAME", "git-project-updater"),
+    ("GIT_AUTHOR_EMAIL", "git-project-updater@localhost"),
+    ("GIT_COMMITTER_NAME", "git-project-updater"),
+    ("GIT_COMMITTER_EMAIL", "git-project-updater@localhost"),
+];
+
+/// Derives a default-branch tarball URL for hosts with a known archive
+/// endpoint (currently GitHub's codeload).
+pub fn default_archive_url(repo_url: &str) -> Option<String> {
+    let path = repo_url
+        .strip_prefix("https://github.com/")
+        .or_else(|| repo_url.strip_prefix("http://github.com/"))
+        .or_else(|| repo_url.strip_prefix("git@github.com:"))
+        .or_else(|| repo_url.strip_prefix("ssh://git@github.com/"))?;
+    let path = path.trim_end_matches('/').trim_end_matches(".git");
+    let (owner, repo) = path.split_once('/')?;
+    if owner.is_empty() || repo.is_empty() || repo.contains('/') {
+        return None;
+    }
+    Some(format!(
+        "https://codeload.github.com/{}/{}/tar.gz/HEAD",
+        owner, repo
+    ))
+}
+
+/// Downloads a `.tar.gz` snapshot into `target_path` (dropping the archive's
+/// top-level directory) and turns it into a single-commit git repository.
+/// The result has no upstream history.
+pub fn import_from_archive(
+    archive_url: &str,
+    target_path: &Path,
+    project_name: &str,
+    progress_bar: &ProgressBar,
+) -> Result<(), ProjectError> {
+    let archive_error = |message: String| ProjectError::Archive {
+        project_name: project_name.to_string(),
+        message,
+    };
+
+    let msg = format!(
+        "Project '{}': Downloading archive '{}'...",
+        project_name,
+        redact_url(archive_url)
+    );
+    progress_bar.set_message(msg.clone());
+    log_to_file(LogLevel::Info, &msg);
+
+    let response = ureq::get(archive_url)
+        .call()
+        .map_err(|e| archive_error(format!("download failed: {}", e)))?;
+
+    fs::create_dir_all(target_path)
+        .map_err(|e| archive_error(format!("cannot create '{}': {}", target_path.display(), e)))?;
+    if let Err(e) = unpack_stripped(response.into_reader(), target_path) {
+        let _ = fs::remove_dir_all(target_path);
+        return Err(archive_error(format!("extraction failed: {}", e)));
+    }
+
+    let archive_source = redact_url(archive_url);
+    let init_steps: [&[&str]; 4] = [
+        &["init", "--quiet"],
+        &["add", "--all"],
+        &[
+            "commit",
+            "--quiet",
+            "--no-verify",
+            "-m",
+            "Imported from archive",
+        ],
+        &["config", ARCHIVE_SOURCE_CONFIG_KEY, &archive_source],
+    ];
+    for args in init_steps {
+        let started = Instant::now();
+        // A fixed identity for the import commit: hosts running from cron
+        // often have no user.email configured.
+        let output = Command::new("git")
+            .current_dir(target_path)
+            .args(args)
+            .envs(IMPORT_IDENTITY)
+            .output();
+        log_trace(
+            &redact_text(&format!("git {}", args.join(" "))),
+            Some(target_path),
+            output.as_ref().ok().and_then(|output| output.status.code()),
+            started.elapsed(),
+        );
+        // Like a failed extraction, a half-initialized repository must not
+        // stay behind: later runs would take it for an empty one and skip it.
+        let failure = match output {
+            Err(e) => Some(format!("git {} failed to start: {}", args[0], e)),
+            Ok(output) if !output.status.success() => Some(format!(
+                "git {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Ok(_) => None,
+        };
+        if let Some(message) = failure {
+            let _ = fs::remove_dir_all(target_path);
+            return Err(archive_error(message));
+        }
+    }
+
+    log_to_file(
+        LogLevel::Warning,
+        &format!(
+            "Project '{}': Imported from archive into '{}'. The repository has no upstream git history and will not be pulled.",
+            project_name,
+            target_path.display()
+        ),
+    );
+    Ok(())
+}
+
+/// Unpacks a gzipped tarball into `target_path`, dropping the leading
+/// directory component that host-generated archives wrap everything in.
+fn unpack_stripped(reader: impl Read, target_path: &Path) -> io::Result<()> {
+    let canonical_target = target_path.canonicalize()?;
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let stripped: PathBuf = entry.path()?.components().skip(1).collect();
+        if stripped.as_os_str().is_empty() {
+            continue;
+        }
+        if !stripped
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsafe path in archive: {}", stripped.display()),
+            ));
+        }
+
+        let destination = canonical_target.join(&stripped);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+            // Refuse to write through a symlink that escapes the target.
+            if !parent.canonicalize()?.starts_with(&canonical_target) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("archive entry escapes target: {}", stripped.display()),
+                ));
+            }
+        }
+        entry.unpack(&destination)?;
+    }
+    Ok(())
+}
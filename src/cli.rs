@@ -0,0 +1,561 @@
+use crate::error::AppError;
+use crate::init::{DEFAULT_INIT_PATH, SAMPLE_CONFIG_JSON};
+use crate::logger::LogTarget;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+pub struct CliArgs {
+    pub config_file: PathBuf,
+    pub branch: Option<String>,
+    /// Process only these projects (`--only`, repeatable).
+    pub only: Vec<String>,
+    /// Leave these projects out (`--exclude`, repeatable), after `only`.
+    pub exclude: Vec<String>,
+    /// Only process projects whose config entry changed since this git ref.
+    pub changed_since: Option<String>,
+    /// Skip projects that synced successfully less than this long ago.
+    pub since: Option<Duration>,
+    pub changelog_md: Option<PathBuf>,
+    /// Report clones that differ from the config instead of updating them.
+    pub detect_drift: bool,
+    /// Print a health table of every clone instead of updating them.
+    pub verify: bool,
+    /// With `verify`, also run `git fsck`.
+    pub fsck: bool,
+    /// Write the project dependency graph here and exit without running git.
+    pub graph_dot: Option<PathBuf>,
+    pub max_load: Option<f64>,
+    /// Projects processed at once; defaults to the number of CPUs.
+    pub jobs: Option<usize>,
+    /// Use `jobs` as given even above the soft cap for this machine.
+    pub force_jobs: bool,
+    /// Project hooks run at once, independently of `jobs`.
+    pub hook_jobs: Option<usize>,
+    /// Extra attempts for clones and pulls that hit a network error.
+    pub retries: Option<u32>,
+    /// Write the JSON run summary here after the run.
+    pub report: Option<PathBuf>,
+    pub report_ndjson: Option<PathBuf>,
+    pub max_output_lines: Option<usize>,
+    pub log_target: Option<LogTarget>,
+    /// Log file path, overriding `log_file` in the config.
+    pub log_file: Option<String>,
+    pub post_status: bool,
+    /// Print each project's resolved path and exit without running git.
+    pub list: bool,
+    /// Print the effective config as JSON and exit without running git.
+    pub print_config: bool,
+    /// After the run, list projects grouped by status, failures first.
+    pub group_by_status: bool,
+    /// Start no further projects once one has failed.
+    pub fail_fast: bool,
+    /// Check every remote with `git ls-remote` before processing any project.
+    pub preflight: bool,
+    pub on_complete: Option<String>,
+    pub remove_stale_locks: bool,
+    pub trace: bool,
+    /// Never color status lines, like setting `NO_COLOR`.
+    pub no_color: bool,
+    pub dry_run: bool,
+    /// Treat every project as `fetch_only`.
+    pub fetch_only: bool,
+    /// Stash local changes instead of skipping a project with a dirty tree.
+    pub force: bool,
+    /// Run a full `git gc` in every repository after syncing it.
+    pub gc: bool,
+    /// Point existing clones' remotes at the configured URL when it changed.
+    pub update_remotes: bool,
+    /// Reset hard to the remote branch when a pull hits a merge conflict.
+    pub reset_on_conflict: bool,
+    pub print_paths: bool,
+    /// Prefix each `--print-paths` entry with the project name and a tab.
+    pub print_names: bool,
+    /// Terminate `--print-paths` entries with NUL instead of a newline.
+    pub print0: bool,
+    /// No progress output at all; only errors reach the console.
+    pub quiet: bool,
+    /// Echo every log line to stderr as it is written.
+    pub verbose: bool,
+    /// `Some(true)` for `--progress`, `Some(false)` for `--no-progress`,
+    /// `None` to decide from the environment.
+    pub progress: Option<bool>,
+}
+
+pub enum CliCommand {
+    Run(Box<CliArgs>),
+    Help,
+    /// `--init [path]`: write a starter config instead of running.
+    Init {
+        path: PathBuf,
+        force: bool,
+    },
+}
+
+pub fn parse_args(args: &[String]) -> Result<CliCommand, AppError> {
+    if args.len() < 2 {
+        return Ok(CliCommand::Help);
+    }
+
+    let mut cli_args = CliArgs::default();
+    let mut config_file: Option<PathBuf> = None;
+    let mut init = false;
+    let mut iter = args.iter().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+
+        match flag {
+            "--help" | "-h" => return Ok(CliCommand::Help),
+            "--init" => {
+                init = true;
+                if let Some(path) = inline_value {
+                    config_file = Some(PathBuf::from(path));
+                }
+            }
+            "--branch" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.branch = Some(value);
+            }
+            "--only" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.only.push(value);
+            }
+            "--exclude" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.exclude.push(value);
+            }
+            "--changed-since" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.changed_since = Some(value);
+            }
+            "--since" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.since = Some(parse_duration(&value).ok_or_else(|| {
+                    AppError::Usage(format!(
+                        "--since expects a duration such as 30m, 12h or 7d, got '{}'",
+                        value
+                    ))
+                })?);
+            }
+            "--changelog-md" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.changelog_md = Some(PathBuf::from(value));
+            }
+            "--graph-dot" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.graph_dot = Some(PathBuf::from(value));
+            }
+            "--post-status" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--post-status does not take a value".to_string(),
+                    ));
+                }
+                cli_args.post_status = true;
+            }
+            "--remove-stale-locks" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--remove-stale-locks does not take a value".to_string(),
+                    ));
+                }
+                cli_args.remove_stale_locks = true;
+            }
+            "--dry-run" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--dry-run does not take a value".to_string(),
+                    ));
+                }
+                cli_args.dry_run = true;
+            }
+            "--fetch-only" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--fetch-only does not take a value".to_string(),
+                    ));
+                }
+                cli_args.fetch_only = true;
+            }
+            "--force" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage("--force does not take a value".to_string()));
+                }
+                cli_args.force = true;
+            }
+            "--gc" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage("--gc does not take a value".to_string()));
+                }
+                cli_args.gc = true;
+            }
+            "--update-remotes" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--update-remotes does not take a value".to_string(),
+                    ));
+                }
+                cli_args.update_remotes = true;
+            }
+            "--reset-on-conflict" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--reset-on-conflict does not take a value".to_string(),
+                    ));
+                }
+                cli_args.reset_on_conflict = true;
+            }
+            "--trace" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage("--trace does not take a value".to_string()));
+                }
+                cli_args.trace = true;
+            }
+            "--list" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage("--list does not take a value".to_string()));
+                }
+                cli_args.list = true;
+            }
+            "--verify" | "--fsck" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(format!("{} does not take a value", flag)));
+                }
+                cli_args.verify = true;
+                cli_args.fsck |= flag == "--fsck";
+            }
+            "--print-config" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--print-config does not take a value".to_string(),
+                    ));
+                }
+                cli_args.print_config = true;
+            }
+            "--detect-drift" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--detect-drift does not take a value".to_string(),
+                    ));
+                }
+                cli_args.detect_drift = true;
+            }
+            "--fail-fast" | "--continue-on-error" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(format!("{} does not take a value", flag)));
+                }
+                cli_args.fail_fast = flag == "--fail-fast";
+            }
+            "--preflight" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--preflight does not take a value".to_string(),
+                    ));
+                }
+                cli_args.preflight = true;
+            }
+            "--group-by-status" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--group-by-status does not take a value".to_string(),
+                    ));
+                }
+                cli_args.group_by_status = true;
+            }
+            "--no-color" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--no-color does not take a value".to_string(),
+                    ));
+                }
+                cli_args.no_color = true;
+            }
+            "--print-paths" | "--print-names" | "--print0" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(format!("{} does not take a value", flag)));
+                }
+                match flag {
+                    "--print-paths" => cli_args.print_paths = true,
+                    "--print-names" => cli_args.print_names = true,
+                    _ => cli_args.print0 = true,
+                }
+            }
+            "--quiet" | "--verbose" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(format!("{} does not take a value", flag)));
+                }
+                match flag {
+                    "--quiet" => cli_args.quiet = true,
+                    _ => cli_args.verbose = true,
+                }
+            }
+            "--progress" | "--no-progress" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(format!("{} does not take a value", flag)));
+                }
+                cli_args.progress = Some(flag == "--progress");
+            }
+            "--on-complete" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.on_complete = Some(value);
+            }
+            "--report" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.report = Some(PathBuf::from(value));
+            }
+            "--report-ndjson" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.report_ndjson = Some(PathBuf::from(value));
+            }
+            "--log-target" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                cli_args.log_target = Some(match value.as_str() {
+                    "file" => LogTarget::File,
+                    "syslog" => LogTarget::Syslog,
+                    "stderr" => LogTarget::Stderr,
+                    _ => {
+                        return Err(AppError::Usage(format!(
+                            "--log-target expects file, syslog or stderr, got '{}'",
+                            value
+                        )))
+                    }
+                });
+            }
+            "--log-file" => {
+                cli_args.log_file = Some(flag_value(flag, inline_value, &mut iter)?);
+            }
+            "--max-output-lines" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                let max_lines =
+                    value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| {
+                            AppError::Usage(format!(
+                                "--max-output-lines expects a positive integer, got '{}'",
+                                value
+                            ))
+                        })?;
+                cli_args.max_output_lines = Some(max_lines);
+            }
+            "--jobs" | "-j" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                let jobs = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| {
+                        AppError::Usage(format!(
+                            "--jobs expects a positive integer, got '{}'",
+                            value
+                        ))
+                    })?;
+                cli_args.jobs = Some(jobs);
+            }
+            "--force-jobs" => {
+                if inline_value.is_some() {
+                    return Err(AppError::Usage(
+                        "--force-jobs does not take a value".to_string(),
+                    ));
+                }
+                cli_args.force_jobs = true;
+            }
+            "--hook-jobs" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                let hook_jobs =
+                    value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| {
+                            AppError::Usage(format!(
+                                "--hook-jobs expects a positive integer, got '{}'",
+                                value
+                            ))
+                        })?;
+                cli_args.hook_jobs = Some(hook_jobs);
+            }
+            "--retries" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                let retries = value.parse::<u32>().map_err(|_| {
+                    AppError::Usage(format!(
+                        "--retries expects a non-negative integer, got '{}'",
+                        value
+                    ))
+                })?;
+                cli_args.retries = Some(retries);
+            }
+            "--max-load" => {
+                let value = flag_value(flag, inline_value, &mut iter)?;
+                let max_load = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|load| *load > 0.0)
+                    .ok_or_else(|| {
+                        AppError::Usage(format!(
+                            "--max-load expects a positive number, got '{}'",
+                            value
+                        ))
+                    })?;
+                cli_args.max_load = Some(max_load);
+            }
+            _ if flag.starts_with('-') => {
+                return Err(AppError::Usage(format!("Unknown option '{}'", flag)));
+            }
+            _ => {
+                if config_file.is_some() {
+                    return Err(AppError::Usage(format!(
+                        "Unexpected extra argument '{}'",
+                        arg
+                    )));
+                }
+                config_file = Some(PathBuf::from(arg));
+            }
+        }
+    }
+
+    if init {
+        return Ok(CliCommand::Init {
+            path: config_file.unwrap_or_else(|| PathBuf::from(DEFAULT_INIT_PATH)),
+            force: cli_args.force,
+        });
+    }
+
+    if (cli_args.print_names || cli_args.print0) && !cli_args.print_paths {
+        return Err(AppError::Usage(
+            "--print-names and --print0 require --print-paths".to_string(),
+        ));
+    }
+
+    if cli_args.quiet && cli_args.verbose {
+        return Err(AppError::Usage(
+            "--quiet and --verbose cannot be combined".to_string(),
+        ));
+    }
+    if (cli_args.quiet || cli_args.verbose) && cli_args.progress == Some(true) {
+        return Err(AppError::Usage(
+            "--progress cannot be combined with --quiet or --verbose".to_string(),
+        ));
+    }
+
+    cli_args.config_file =
+        config_file.ok_or_else(|| AppError::Usage("Missing <config_file> argument".to_string()))?;
+    Ok(CliCommand::Run(Box::new(cli_args)))
+}
+
+fn flag_value<'a>(
+    flag: &str,
+    inline_value: Option<String>,
+    iter: &mut impl Iterator<Item = &'a String>,
+) -> Result<String, AppError> {
+    let value = match inline_value {
+        Some(value) => value,
+        None => iter
+            .next()
+            .cloned()
+            .ok_or_else(|| AppError::Usage(format!("Option '{}' requires a value", flag)))?,
+    };
+    if value.is_empty() {
+        return Err(AppError::Usage(format!(
+            "Option '{}' requires a non-empty value",
+            flag
+        )));
+    }
+    Ok(value)
+}
+
+/// Parses `<n><unit>` with unit `s`, `m`, `h`, `d` or `w`, e.g. `12h`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let unit_start = value.find(|c: char| !c.is_ascii_digit())?;
+    let (count, unit) = value.split_at(unit_start);
+    let count: u64 = count.parse().ok()?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(count.checked_mul(unit_secs)?))
+}
+
+pub fn print_usage() {
+    println!("Usage: git_project_updater <config_file.json|.yaml|.toml> [options]");
+    println!("       git_project_updater --init [path] [--force]");
+    println!("A tool to clone and update multiple Git repositories based on a JSON config.");
+    println!("\nOptions:");
+    println!(
+        "  --branch <name>        Pull only <name> in every project, overriding pull_branches"
+    );
+    println!("  --only <name>          Process only project <name>; repeat to select several");
+    println!("  --exclude <name>       Skip project <name>; repeatable, applied after --only");
+    println!(
+        "  --changed-since <ref>  Only process projects whose config entry changed since git <ref>"
+    );
+    println!("  --since <duration>     Skip projects synced successfully within <duration> (e.g. 12h, 7d)");
+    println!(
+        "  --changelog-md <path>  Write a Markdown summary of the new commits pulled in this run"
+    );
+    println!("  --graph-dot <path>     Write the depends_on graph as Graphviz DOT to <path> and exit without running git");
+    println!(
+        "  -j, --jobs <n>         Process up to <n> projects at once (default: number of CPUs)"
+    );
+    println!("  --force-jobs           Use --jobs as given even above the soft cap for this machine's CPUs and open files");
+    println!(
+        "  --hook-jobs <n>        Run at most <n> project hooks at once, independently of --jobs"
+    );
+    println!("  --retries <n>          Retry a clone or pull up to <n> times after a network error, with backoff");
+    println!("  --max-load <n>         Wait before starting a project while the 1-minute load average exceeds <n>");
+    println!("  --report <path>        Write a JSON summary of the run (counts, per-project status and durations) to <path>");
+    println!(
+        "  --report-ndjson <path> Append one JSON line per project to <path> as each finishes"
+    );
+    println!("  --log-target <target>  Write the log to file (project_fetcher.log, default), syslog or stderr");
+    println!(
+        "  --log-file <path>      Write the log file to <path> instead of ./project_fetcher.log"
+    );
+    println!("  --max-output-lines <n> Log only the first and last <n> lines of captured git/hook output");
+    println!("  --on-complete <cmd>    Run <cmd> through the shell after the run with the JSON report on stdin");
+    println!("  --list                 Print each project's URL, resolved path and whether it is cloned, then exit");
+    println!("  --print-config         Print the effective config (templates, variables, rewrites, resolved paths) as JSON, then exit");
+    println!("  --detect-drift         Report clones whose remote, tag, branch or commit differ from the config; change nothing");
+    println!("  --verify               Print a health table of every clone (repository, branch, local changes, remote); change nothing");
+    println!("  --fsck                 Like --verify, and also run 'git fsck' in every clone");
+    println!("  --preflight            Check that every remote answers 'git ls-remote' before processing any project");
+    println!("  --fail-fast            Start no further projects after the first failure; running ones finish");
+    println!("  --continue-on-error    Process every project even after failures (default)");
+    println!("  --group-by-status      After the run, list projects grouped as failed, skipped, cloned, updated, up to date");
+    println!("  --post-status          After the run, list repositories left dirty, detached or diverged");
+    println!("  --remove-stale-locks   Delete index.lock files older than 10 minutes and retry the git command once");
+    println!("  --dry-run              Print the git commands that would change repositories instead of running them");
+    println!("  --fetch-only           Only fetch remote-tracking refs for every project; never merge or check out");
+    println!("  --force                Stash uncommitted changes and pull instead of skipping the project");
+    println!("  --gc                   Run a full 'git gc' in every repository after syncing it");
+    println!("  --update-remotes       Change an existing clone's remote URL to the config's 'url' when they differ");
+    println!("  --reset-on-conflict    Reset hard to the remote branch when a pull conflicts, discarding local work");
+    println!("  --trace                Log every git/hook subprocess with its directory, exit code and duration");
+    println!("  --print-paths          After the run, print each existing project's absolute path to stdout");
+    println!("  --print-names          With --print-paths, print '<name>\\t<path>' instead of just the path");
+    println!(
+        "  --print0               With --print-paths, end entries with NUL instead of a newline"
+    );
+    println!(
+        "  --quiet                No progress bar or progress lines; only errors reach the console"
+    );
+    println!("  --verbose              Echo every log line to stderr as it is written (implies --no-progress)");
+    println!("  --progress             Always draw the animated progress bar");
+    println!("  --no-progress          Print plain progress lines (default in CI or when stderr is not a terminal)");
+    println!(
+        "  --no-color             Do not color error and warning lines (also disabled by NO_COLOR)"
+    );
+    println!("  --init [path]          Write a starter config to [path] (default git-fetcher.json; .yaml/.toml get comments) and exit");
+    println!("  -h, --help             Show this help text");
+    println!("\nConfig file format example:");
+    println!("\n{}", SAMPLE_CONFIG_JSON);
+}
@@ -1,25 +1,325 @@
 use crate::error::ConfigError;
-use serde::Deserialize;
+use crate::git_utils::{is_git_repo, DEFAULT_REMOTE};
+use crate::logger::{log_to_file, LogFormat, LogLevel};
+use crate::project_logic::resolve_project_path;
+use crate::redact::{redact_proxy, redact_url};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct AppConfig {
     pub global_config: Option<GlobalConfig>,
     pub projects: Vec<ProjectConfig>,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GlobalConfig {
     pub default_clone_parent_directory: Option<String>,
+    /// What a relative or absent `default_clone_parent_directory` is
+    /// resolved against.
+    pub base: Option<CloneParentBase>,
+    pub notify_email: Option<NotifyEmailConfig>,
+    /// Non-zero git exit codes treated as success for every project.
+    pub success_exit_codes: Option<Vec<i32>>,
+    /// Characters of each commit hash shown in logs and reports (4-40).
+    pub hash_length: Option<usize>,
+    /// Delay before the first `--retries` attempt, doubled for each one after.
+    pub retry_delay_ms: Option<u64>,
+    /// How much of each `--retries` delay is randomized, so parallel
+    /// projects do not retry in lockstep.
+    pub retry_jitter: Option<RetryJitter>,
+    /// Seconds any single git command may run before it is killed.
+    pub command_timeout_secs: Option<u64>,
+    /// `pull_branches` for projects that leave it out.
+    pub default_pull_branches: Option<Vec<String>>,
+    /// What `pull_branches: []` pulls; an absent list falls back to
+    /// `default_pull_branches`, then the current branch.
+    pub empty_pull_branches_means: Option<EmptyPullBranches>,
+    /// Log file used instead of `project_fetcher.log` in the working
+    /// directory; `--log-file` takes precedence.
+    pub log_file: Option<String>,
+    /// `text` (default) or `json`, one object per line for log collectors.
+    pub log_format: Option<LogFormat>,
+    /// Directory receiving one `<project>.log` per project.
+    pub project_log_dir: Option<String>,
+    /// Keep project lines out of the shared log; needs `project_log_dir`.
+    pub project_log_only: Option<bool>,
+    /// Size in MB at which `project_fetcher.log` is rotated.
+    pub log_rotate_size_mb: Option<u64>,
+    /// Rotated log files kept; 0 deletes the log instead of rotating it.
+    pub log_rotate_keep: Option<usize>,
+    /// Run `git gc --auto` in every repository after syncing it.
+    pub run_gc: Option<bool>,
+    /// Delete remote-tracking branches that are gone upstream before pulling.
+    pub prune: Option<bool>,
+    /// File holding an HTTPS access token (or `username:token`) for git.
+    pub credentials_file: Option<String>,
+    /// Proxy for git's HTTP(S) traffic (`http.proxy`).
+    pub proxy: Option<String>,
+    /// URL prefixes replaced in every project's `url`, like git's `insteadOf`.
+    pub url_rewrite: Option<Vec<UrlRewrite>>,
+    /// Projects of one host processed at once, to stay under its rate limits.
+    pub max_concurrent_per_host: Option<usize>,
 }
 
-#[derive(Deserialize, Debug)]
+impl GlobalConfig {
+    /// `credentials_file` with `~` expanded, made absolute since git runs
+    /// the credential helper from inside each repository.
+    pub fn credentials_file_path(&self) -> Option<PathBuf> {
+        self.credentials_file.as_ref().map(|file| {
+            let file_path = PathBuf::from(shellexpand::tilde(file).to_string());
+            std::path::absolute(&file_path).unwrap_or(file_path)
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CloneParentBase {
+    /// The directory containing the config file.
+    #[default]
+    ConfigDir,
+    /// The platform data directory for this tool, e.g.
+    /// `$XDG_DATA_HOME/git-project-updater` on Linux.
+    DataDir,
+}
+
+/// Per-user data directory for this tool, or `None` when the platform's
+/// home directory cannot be determined.
+pub fn platform_data_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "git-project-updater").map(|dirs| dirs.data_dir().to_path_buf())
+}
+
+/// Directory relative project paths are resolved against:
+/// `default_clone_parent_directory` (relative to `base`), the base itself when
+/// that is unset, or `cwd` when it is empty. Falls back to `config_file_dir`
+/// when the platform data directory is unknown.
+pub fn clone_parent_dir(
+    global_config: Option<&GlobalConfig>,
+    config_file_dir: &Path,
+    cwd: &Path,
+) -> PathBuf {
+    let base_dir = match global_config.and_then(|gc| gc.base) {
+        Some(CloneParentBase::DataDir) => {
+            platform_data_dir().unwrap_or_else(|| config_file_dir.to_path_buf())
+        }
+        Some(CloneParentBase::ConfigDir) | None => config_file_dir.to_path_buf(),
+    };
+    match global_config.and_then(|gc| gc.default_clone_parent_directory.as_deref()) {
+        None => base_dir,
+        Some("") => cwd.to_path_buf(),
+        Some(parent_dir_str) => {
+            let expanded_parent_dir = shellexpand::tilde(parent_dir_str).to_string();
+            let parent_path = Path::new(&expanded_parent_dir);
+            if parent_path.is_absolute() {
+                parent_path.to_path_buf()
+            } else {
+                base_dir.join(parent_path)
+            }
+        }
+    }
+}
+
+/// One `url_rewrite` rule: a project `url` starting with `from` has that
+/// prefix replaced by `to`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UrlRewrite {
+    pub from: String,
+    pub to: String,
+}
+
+/// What an empty `pull_branches` list pulls.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyPullBranches {
+    /// Whatever branch is checked out, same as leaving `pull_branches` out.
+    #[default]
+    Current,
+    /// The remote's default branch.
+    Default,
+    /// Every branch on the remote.
+    All,
+}
+
+/// Randomization applied to each `--retries` delay.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryJitter {
+    /// Exactly the doubling delay.
+    None,
+    /// Anywhere between zero and the doubling delay.
+    Full,
+    /// Half the doubling delay plus a random part of the other half.
+    #[default]
+    Equal,
+}
+
+/// Summary email sent through the local `sendmail` at the end of a run.
+/// Only delivered when the binary is built with the `email` feature.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(not(feature = "email"), allow(dead_code))]
+pub struct NotifyEmailConfig {
+    pub to: Vec<String>,
+    pub from: Option<String>,
+    pub subject: Option<String>,
+    pub sendmail_command: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ProjectConfig {
     pub project: String,
     pub url: String,
     pub path: String,
     pub pull_branches: Option<Vec<String>>,
+    /// Tag to fetch and check out (detached) instead of pulling branches.
+    pub checkout_tag: Option<String>,
+    /// Name of an entry in the top-level `templates` map whose fields fill in
+    /// anything this project leaves unset.
+    pub extends: Option<String>,
+    /// Remote to clone as, fetch and pull from; `origin` when absent.
+    pub remote: Option<String>,
+    /// Private key used for every git command of this project, through
+    /// `GIT_SSH_COMMAND`.
+    pub ssh_key: Option<String>,
+    /// Local repository passed to `git clone --reference` to share objects.
+    pub reference: Option<String>,
+    /// Copy borrowed objects after cloning so the clone no longer depends on
+    /// `reference`. Defaults to true; disabling it leaves the clone broken if
+    /// the reference repository is deleted or pruned.
+    pub dissociate: Option<bool>,
+    /// Back up and re-clone an existing checkout whose origin was swapped for
+    /// a repository with unrelated history.
+    pub reclone_on_url_change: Option<bool>,
+    /// When the project's directory is missing, move a clone of the same
+    /// `url` found in a sibling directory there instead of cloning again.
+    pub move_on_path_change: Option<bool>,
+    /// Non-zero git exit codes treated as success; replaces the global list.
+    pub success_exit_codes: Option<Vec<i32>>,
+    /// Shell command run in the project directory before any checkout/pull.
+    pub pre_update: Option<String>,
+    /// Fail the project instead of skipping its update when `pre_update` fails.
+    pub fail_on_pre_update_error: Option<bool>,
+    /// Shell command run in the project directory after a successful clone
+    /// or pull, e.g. `npm ci`.
+    pub post_update_command: Option<String>,
+    /// Stay on the last branch of `pull_branches` instead of restoring the
+    /// branch the repository was on before the update.
+    pub leave_on_last_pulled: Option<bool>,
+    /// Only fetch the configured branches (or all remotes) into their
+    /// remote-tracking refs; never check out, merge or touch the work tree.
+    pub fetch_only: Option<bool>,
+    /// Clone and track only this branch (`--branch <name> --single-branch`).
+    pub single_branch: Option<String>,
+    /// Keep a bare clone without a work tree (`git clone --bare`).
+    pub bare: Option<bool>,
+    /// Keep a mirror of every remote ref (`git clone --mirror`), e.g. for
+    /// backups.
+    pub mirror: Option<bool>,
+    /// Run `git gc --auto` after syncing; overrides the global `run_gc`.
+    pub run_gc: Option<bool>,
+    /// Prune stale remote-tracking branches; overrides the global `prune`.
+    pub prune: Option<bool>,
+    /// Run `git reset --hard` and `git clean -fdx` after pulling. Destroys
+    /// local changes and untracked files.
+    pub clean_worktree: Option<bool>,
+    /// When a pull stops on a merge conflict, reset hard to the remote branch.
+    /// Discards local changes and commits.
+    pub reset_on_conflict: Option<bool>,
+    /// Clone with `--depth`; 0 converts an existing shallow clone to full
+    /// history.
+    pub depth: Option<u32>,
+    /// Fetch this many more commits of history into an existing shallow clone
+    /// before pulling.
+    pub deepen: Option<u32>,
+    /// Run `git submodule update --init --recursive` after cloning or pulling
+    /// and report which submodule pins moved. Also accepted as `submodules`.
+    #[serde(alias = "submodules")]
+    pub init_submodules: Option<bool>,
+    /// Only clone and update the submodules at these paths. Implies
+    /// `init_submodules`.
+    pub submodule_paths: Option<Vec<String>>,
+    /// Branch name `HEAD` should point at while the repository has no commits.
+    pub init_branch: Option<String>,
+    /// Download a tarball snapshot instead of, or when, `git clone` fails.
+    /// Requires the `archive` feature.
+    pub archive_fallback: Option<ArchiveFallback>,
+    /// Tarball to download; derived from `url` for GitHub when absent.
+    #[cfg_attr(not(feature = "archive"), allow(dead_code))]
+    pub archive_url: Option<String>,
+    /// Names of projects that must be processed before this one. The project
+    /// is skipped if any of them fails.
+    pub depends_on: Option<Vec<String>>,
+    /// Fail the project unless `git verify-commit HEAD` accepts the commit
+    /// left checked out after the update.
+    pub verify_signature: Option<bool>,
+    /// Branch the repository must be on after the update.
+    pub expect_branch: Option<String>,
+    /// Commit (full or abbreviated hash) `HEAD` must point at after the update.
+    pub expect_commit: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFallback {
+    /// Only when `git clone` fails.
+    OnFailure,
+    /// Never attempt a git clone for this project.
+    Always,
+}
+
+impl ProjectConfig {
+    pub fn reference_path(&self) -> Option<PathBuf> {
+        self.reference
+            .as_ref()
+            .map(|reference| PathBuf::from(shellexpand::tilde(reference).to_string()))
+    }
+
+    /// Whether the clone has no work tree (`bare` or `mirror`).
+    pub fn is_bare(&self) -> bool {
+        self.bare.unwrap_or(false) || self.mirror.unwrap_or(false)
+    }
+
+    /// The branch list to pull: the project's own `pull_branches` when set,
+    /// even if empty, then its `single_branch`, otherwise the global
+    /// `default_pull_branches`. Projects on a tag or without a work tree
+    /// never inherit the default.
+    pub fn effective_pull_branches<'a>(
+        &'a self,
+        global_config: &'a GlobalConfig,
+    ) -> Option<&'a [String]> {
+        match (&self.pull_branches, &self.single_branch) {
+            (Some(branches), _) => Some(branches),
+            (None, Some(branch)) => Some(std::slice::from_ref(branch)),
+            (None, None) if self.checkout_tag.is_some() || self.is_bare() => None,
+            (None, None) => global_config.default_pull_branches.as_deref(),
+        }
+    }
+
+    /// `--branch`: pull only `branch`, ahead of the project's own list and
+    /// the global default. Projects pinned to a tag stay on it.
+    pub fn override_pull_branches(&mut self, branch: &str) {
+        if self.checkout_tag.is_none() {
+            self.pull_branches = Some(vec![branch.to_string()]);
+        }
+    }
+
+    pub fn remote_name(&self) -> &str {
+        self.remote.as_deref().unwrap_or(DEFAULT_REMOTE)
+    }
+
+    /// `ssh_key` with `~` expanded, made absolute since git runs ssh from
+    /// inside the repository.
+    pub fn ssh_key_path(&self) -> Option<PathBuf> {
+        self.ssh_key.as_ref().map(|key| {
+            let key_path = PathBuf::from(shellexpand::tilde(key).to_string());
+            std::path::absolute(&key_path).unwrap_or(key_path)
+        })
+    }
 }
 
 pub fn load_config_from_file(config_file_path: &Path) -> Result<AppConfig, ConfigError> {
@@ -30,20 +330,507 @@ pub fn load_config_from_file(config_file_path: &Path) -> Result<AppConfig, Confi
     let config_content = fs::read_to_string(config_file_path)
         .map_err(|e| ConfigError::ReadFile(config_file_path.to_path_buf(), e))?;
 
-    let app_config: AppConfig =
-        serde_json::from_str(&config_content).map_err(ConfigError::Parse)?;
+    let mut raw_config = parse_raw_config(config_file_path, &config_content)?;
+    apply_templates(&mut raw_config)?;
+    let mut app_config: AppConfig =
+        serde_json::from_value(raw_config).map_err(ConfigError::Parse)?;
+    expand_config_env_vars(&mut app_config)?;
+    for project_config in &mut app_config.projects {
+        project_config.path = expand_path_tokens(project_config)?;
+    }
+    apply_url_rewrites(&mut app_config)?;
 
     if app_config.projects.is_empty() {
         return Err(ConfigError::NoProjects);
     }
 
+    if let Some(codes) = app_config
+        .global_config
+        .as_ref()
+        .and_then(|gc| gc.success_exit_codes.as_ref())
+    {
+        validate_success_exit_codes("global_config", codes)?;
+    }
+    if app_config
+        .global_config
+        .as_ref()
+        .and_then(|gc| gc.command_timeout_secs)
+        == Some(0)
+    {
+        return Err(ConfigError::Validation {
+            project_name: "global_config".to_string(),
+            message: "command_timeout_secs must be a positive number of seconds".to_string(),
+        });
+    }
+    if app_config
+        .global_config
+        .as_ref()
+        .and_then(|gc| gc.max_concurrent_per_host)
+        == Some(0)
+    {
+        return Err(ConfigError::Validation {
+            project_name: "global_config".to_string(),
+            message: "max_concurrent_per_host must be at least 1".to_string(),
+        });
+    }
+    if app_config
+        .global_config
+        .as_ref()
+        .and_then(|gc| gc.log_rotate_size_mb)
+        == Some(0)
+    {
+        return Err(ConfigError::Validation {
+            project_name: "global_config".to_string(),
+            message: "log_rotate_size_mb must be a positive number of megabytes".to_string(),
+        });
+    }
+    if let Some(gc) = &app_config.global_config {
+        if gc.project_log_only.unwrap_or(false) && gc.project_log_dir.is_none() {
+            return Err(ConfigError::Validation {
+                project_name: "global_config".to_string(),
+                message: "project_log_only requires project_log_dir".to_string(),
+            });
+        }
+    }
+    if let Some(proxy) = app_config
+        .global_config
+        .as_ref()
+        .and_then(|gc| gc.proxy.as_deref())
+    {
+        if let Some(problem) = proxy_format_problem(proxy) {
+            return Err(ConfigError::Validation {
+                project_name: "global_config".to_string(),
+                message: format!("proxy '{}' {}", redact_proxy(proxy), problem),
+            });
+        }
+    }
+    if let Some(file_path) = app_config
+        .global_config
+        .as_ref()
+        .and_then(GlobalConfig::credentials_file_path)
+    {
+        if !file_path.is_file() {
+            return Err(ConfigError::Validation {
+                project_name: "global_config".to_string(),
+                message: format!(
+                    "credentials_file '{}' does not exist or is not a file",
+                    file_path.display()
+                ),
+            });
+        }
+    }
+    if let Some(hash_length) = app_config
+        .global_config
+        .as_ref()
+        .and_then(|gc| gc.hash_length)
+    {
+        if !(4..=40).contains(&hash_length) {
+            return Err(ConfigError::Validation {
+                project_name: "global_config".to_string(),
+                message: format!("hash_length {} is out of range; expected 4-40", hash_length),
+            });
+        }
+    }
+
     for project_config in &app_config.projects {
+        if let Some(template_name) = &project_config.extends {
+            log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Project '{}': Inheriting unset fields from template '{}'.",
+                    project_config.project, template_name
+                ),
+            );
+        }
         validate_project_config(project_config)?;
     }
+    validate_unique_projects(&app_config, config_file_path)?;
 
     Ok(app_config)
 }
 
+/// Rejects two projects with the same name, or whose paths resolve to the
+/// same directory.
+fn validate_unique_projects(
+    app_config: &AppConfig,
+    config_file_path: &Path,
+) -> Result<(), ConfigError> {
+    let mut names = HashSet::new();
+    if let Some(project) = app_config
+        .projects
+        .iter()
+        .find(|p| !names.insert(p.project.as_str()))
+    {
+        return Err(ConfigError::DuplicateName(project.project.clone()));
+    }
+
+    // Resolved the same way as for the run; only equality matters here.
+    let cwd = env::current_dir().unwrap_or_default();
+    let config_file_dir = cwd.join(config_file_path.parent().unwrap_or_else(|| Path::new(".")));
+    let parent_clone_dir =
+        clone_parent_dir(app_config.global_config.as_ref(), &config_file_dir, &cwd);
+    let mut seen: HashMap<PathBuf, &str> = HashMap::new();
+    for project in &app_config.projects {
+        let path = resolve_project_path(project, &parent_clone_dir);
+        if let Some(first) = seen.get(&path) {
+            return Err(ConfigError::DuplicatePath {
+                first: first.to_string(),
+                second: project.project.clone(),
+                path,
+            });
+        }
+        seen.insert(path, &project.project);
+    }
+    Ok(())
+}
+
+/// Parses config text as YAML for `.yaml`/`.yml` files and JSON for `.json`.
+/// Other extensions try JSON first, then YAML.
+fn parse_raw_config(config_file_path: &Path, content: &str) -> Result<Value, ConfigError> {
+    let extension = config_file_path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("json") => Ok(serde_json::from_str(content)?),
+        Some("yaml" | "yml") => Ok(serde_yaml::from_str(content)?),
+        Some("toml") => Ok(toml::from_str(content)?),
+        _ => serde_json::from_str(content).or_else(|json| {
+            serde_yaml::from_str(content)
+                .map_err(|yaml| ConfigError::ParseUnknownFormat { json, yaml })
+        }),
+    }
+}
+
+/// Names of projects whose entry, after applying templates, differs between
+/// the config file and `old_config` (its content at an earlier revision).
+/// `None` means every project should run: `global_config` changed, or one
+/// of the two versions cannot be parsed.
+pub fn changed_project_names(config_file_path: &Path, old_config: &str) -> Option<HashSet<String>> {
+    let parse = |content: &str, label: &str| -> Option<Value> {
+        let parsed = parse_raw_config(config_file_path, content)
+            .and_then(|mut raw_config| apply_templates(&mut raw_config).map(|()| raw_config));
+        match parsed {
+            Ok(raw_config) => Some(raw_config),
+            Err(e) => {
+                log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "Cannot compare with the {} config ({}); processing all projects.",
+                        label, e
+                    ),
+                );
+                None
+            }
+        }
+    };
+    let current = parse(&fs::read_to_string(config_file_path).ok()?, "current")?;
+    let previous = parse(old_config, "previous")?;
+
+    if current.get("global_config") != previous.get("global_config") {
+        log_to_file(
+            LogLevel::Info,
+            "global_config changed; processing all projects.",
+        );
+        return None;
+    }
+
+    let projects = |raw_config: &Value| -> Vec<(String, Value)> {
+        raw_config
+            .get("projects")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|project| {
+                let name = project.get("project")?.as_str()?;
+                Some((name.to_string(), project.clone()))
+            })
+            .collect()
+    };
+    let previous_projects = projects(&previous);
+    Some(
+        projects(&current)
+            .into_iter()
+            .filter(|(name, project)| {
+                !previous_projects
+                    .iter()
+                    .any(|(previous_name, previous_project)| {
+                        previous_name == name && previous_project == project
+                    })
+            })
+            .map(|(name, _)| name)
+            .collect(),
+    )
+}
+
+/// Substitutes `${VAR}` in each project's `url` and `path` and in
+/// `default_clone_parent_directory`, so tokens and machine-specific paths can
+/// stay out of the config file. `~` is still expanded later, where paths are
+/// resolved.
+fn expand_config_env_vars(app_config: &mut AppConfig) -> Result<(), ConfigError> {
+    if let Some(parent_dir) = app_config
+        .global_config
+        .as_mut()
+        .and_then(|gc| gc.default_clone_parent_directory.as_mut())
+    {
+        *parent_dir = expand_env_vars(
+            parent_dir,
+            "global_config",
+            "default_clone_parent_directory",
+        )?;
+    }
+    for project_config in &mut app_config.projects {
+        project_config.url = expand_env_vars(&project_config.url, &project_config.project, "url")?;
+        project_config.path =
+            expand_env_vars(&project_config.path, &project_config.project, "path")?;
+    }
+    Ok(())
+}
+
+/// Replaces `{project}`, `{host}`, `{org}` and `{repo}` in a project's `path`,
+/// so a layout like `{host}/{org}/{repo}` need not be spelled out per
+/// project. The URL parts come from the `url` as written, before
+/// `url_rewrite`, so the layout is the same on every machine.
+fn expand_path_tokens(config: &ProjectConfig) -> Result<String, ConfigError> {
+    let invalid = |message: String| ConfigError::Validation {
+        project_name: config.project.clone(),
+        message,
+    };
+    let url_parts = url_host_and_repo_path(&config.url);
+    let mut expanded = String::with_capacity(config.path.len());
+    let mut rest = config.path.as_str();
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            return Err(invalid("path has an unterminated '{' token".to_string()));
+        };
+        let token = &rest[start + 1..start + end];
+        let value = match token {
+            "project" => Some(config.project.as_str()),
+            "host" => url_parts.map(|(host, _)| host),
+            "org" => url_parts
+                .and_then(|(_, repo_path)| repo_path.rsplit_once('/'))
+                .map(|(org, _)| org),
+            "repo" => url_parts.map(|(_, repo_path)| repo_path.rsplit('/').next().unwrap_or(repo_path)),
+            _ => {
+                return Err(invalid(format!(
+                    "path uses unknown token '{{{}}}'; expected {{project}}, {{host}}, {{org}} or {{repo}}",
+                    token
+                )))
+            }
+        };
+        let Some(value) = value.filter(|value| !value.is_empty()) else {
+            return Err(invalid(format!(
+                "path uses '{{{}}}', but url '{}' has no {}",
+                token,
+                redact_url(&config.url),
+                token
+            )));
+        };
+        expanded.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Splits a remote URL into its host and repository path without `.git`:
+/// `https://user@host:8443/org/repo.git` and `git@host:org/repo.git` both
+/// give `("host", "org/repo")`. Local paths have no host and give `None`.
+pub fn url_host_and_repo_path(url: &str) -> Option<(&str, &str)> {
+    let (host_part, repo_path) = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').unwrap_or((rest, "")),
+        None => url
+            .split_once(':')
+            .filter(|(target, _)| !target.contains('/'))?,
+    };
+    let host_port = host_part.rsplit('@').next().unwrap_or_default();
+    let host = match host_port.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => host_port.split(':').next().unwrap_or_default(),
+    };
+    let repo_path = repo_path.trim_matches('/');
+    Some((host, repo_path.strip_suffix(".git").unwrap_or(repo_path)))
+}
+
+/// Rewrites each project's `url` with the longest matching `url_rewrite`
+/// prefix, so one committed config can clone over https on one machine and
+/// ssh on another. The rewritten URL is the one cloned, checked against the
+/// remote and logged.
+fn apply_url_rewrites(app_config: &mut AppConfig) -> Result<(), ConfigError> {
+    let Some(rules) = app_config
+        .global_config
+        .as_ref()
+        .and_then(|gc| gc.url_rewrite.as_ref())
+    else {
+        return Ok(());
+    };
+    if rules.iter().any(|rule| rule.from.is_empty()) {
+        return Err(ConfigError::Validation {
+            project_name: "global_config".to_string(),
+            message: "url_rewrite entries need a non-empty 'from' prefix".to_string(),
+        });
+    }
+    for project_config in &mut app_config.projects {
+        let best_match = rules
+            .iter()
+            .filter(|rule| project_config.url.starts_with(&rule.from))
+            .max_by_key(|rule| rule.from.len());
+        if let Some(rule) = best_match {
+            project_config.url = format!("{}{}", rule.to, &project_config.url[rule.from.len()..]);
+        }
+    }
+    Ok(())
+}
+
+/// Replaces every `${VAR}` in `value` with the variable from the process
+/// environment. Only the braced form is recognised, so a lone `$` is kept.
+/// The error names the variable but never the value, which may hold a token.
+fn expand_env_vars(value: &str, owner: &str, field: &str) -> Result<String, ConfigError> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            return Err(ConfigError::Validation {
+                project_name: owner.to_string(),
+                message: format!("{} has an unterminated '${{' variable reference", field),
+            });
+        };
+        let name = &after[..end];
+        match env::var(name) {
+            Ok(var_value) if !name.is_empty() => expanded.push_str(&var_value),
+            _ => {
+                return Err(ConfigError::Validation {
+                    project_name: owner.to_string(),
+                    message: format!(
+                        "{} references environment variable '{}', which is not set",
+                        field, name
+                    ),
+                })
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Removes the top-level `templates` map and merges the named template's
+/// fields into every project with `extends`. Fields set on the project win.
+fn apply_templates(raw_config: &mut Value) -> Result<(), ConfigError> {
+    let Some(root) = raw_config.as_object_mut() else {
+        return Ok(());
+    };
+    let templates = match root.remove("templates") {
+        None | Some(Value::Null) => Map::new(),
+        Some(Value::Object(templates)) => templates,
+        Some(_) => {
+            return Err(ConfigError::Validation {
+                project_name: "templates".to_string(),
+                message: "templates must be an object mapping names to project fields".to_string(),
+            })
+        }
+    };
+
+    let Some(Value::Array(projects)) = root.get_mut("projects") else {
+        return Ok(());
+    };
+    for project in projects.iter_mut().filter_map(Value::as_object_mut) {
+        let Some(extends) = project.get("extends") else {
+            continue;
+        };
+        let project_name = project
+            .get("project")
+            .and_then(Value::as_str)
+            .unwrap_or("Unknown (empty name)")
+            .to_string();
+        let template_name = extends.as_str().ok_or_else(|| ConfigError::Validation {
+            project_name: project_name.clone(),
+            message: "extends must be a template name".to_string(),
+        })?;
+        let template = match templates.get(template_name) {
+            Some(Value::Object(template)) => template,
+            Some(_) => {
+                return Err(ConfigError::Validation {
+                    project_name,
+                    message: format!("Template '{}' must be an object", template_name),
+                })
+            }
+            None => {
+                return Err(ConfigError::Validation {
+                    project_name,
+                    message: format!("extends refers to unknown template '{}'", template_name),
+                })
+            }
+        };
+        if template.contains_key("extends") {
+            return Err(ConfigError::Validation {
+                project_name,
+                message: format!("Template '{}' cannot itself use extends", template_name),
+            });
+        }
+        for (key, value) in template {
+            if !project.contains_key(key) {
+                project.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reorders `projects` so every project comes after the ones in its
+/// `depends_on`, otherwise keeping config order. Unknown dependencies and
+/// cycles are validation errors.
+pub fn order_by_dependencies(
+    projects: Vec<ProjectConfig>,
+) -> Result<Vec<ProjectConfig>, ConfigError> {
+    let names: HashSet<&str> = projects.iter().map(|p| p.project.as_str()).collect();
+    for project in &projects {
+        for dependency in project.depends_on.iter().flatten() {
+            if !names.contains(dependency.as_str()) {
+                return Err(ConfigError::Validation {
+                    project_name: project.project.clone(),
+                    message: format!("depends_on refers to unknown project '{}'", dependency),
+                });
+            }
+        }
+    }
+
+    let mut remaining: Vec<Option<ProjectConfig>> = projects.into_iter().map(Some).collect();
+    let mut placed: HashSet<String> = HashSet::new();
+    let mut ordered = Vec::with_capacity(remaining.len());
+    while ordered.len() < remaining.len() {
+        let ready = remaining.iter().position(|slot| {
+            slot.as_ref().is_some_and(|project| {
+                project
+                    .depends_on
+                    .iter()
+                    .flatten()
+                    .all(|dependency| placed.contains(dependency))
+            })
+        });
+        let Some(index) = ready else {
+            let cycle: Vec<&str> = remaining
+                .iter()
+                .flatten()
+                .map(|project| project.project.as_str())
+                .collect();
+            return Err(ConfigError::Validation {
+                project_name: cycle[0].to_string(),
+                message: format!(
+                    "depends_on forms a cycle; cannot order projects: {}",
+                    cycle.join(", ")
+                ),
+            });
+        };
+        let project = remaining[index].take().expect("ready slot is occupied");
+        placed.insert(project.project.clone());
+        ordered.push(project);
+    }
+    Ok(ordered)
+}
+
 pub fn validate_project_config(config: &ProjectConfig) -> Result<(), ConfigError> {
     if config.project.is_empty() {
         return Err(ConfigError::Validation {
@@ -57,11 +844,400 @@ pub fn validate_project_config(config: &ProjectConfig) -> Result<(), ConfigError
             message: "Repository URL cannot be empty".to_string(),
         });
     }
+    if let Some(problem) = url_format_problem(&config.url) {
+        return Err(ConfigError::Validation {
+            project_name: config.project.clone(),
+            message: format!("Repository URL '{}' {}", redact_url(&config.url), problem),
+        });
+    }
+    if let Some(remote) = &config.remote {
+        if remote.is_empty() || remote.chars().any(char::is_whitespace) {
+            return Err(ConfigError::Validation {
+                project_name: config.project.clone(),
+                message: format!(
+                    "remote '{}' must be a non-empty name without whitespace",
+                    remote
+                ),
+            });
+        }
+    }
     if config.path.is_empty() {
         return Err(ConfigError::Validation {
             project_name: config.project.clone(),
             message: "Path cannot be empty".to_string(),
         });
     }
+    if let Some(codes) = &config.success_exit_codes {
+        validate_success_exit_codes(&config.project, codes)?;
+    }
+    if config.deepen == Some(0) {
+        return Err(ConfigError::Validation {
+            project_name: config.project.clone(),
+            message: "deepen must be a positive number of commits".to_string(),
+        });
+    }
+    if config.depth.is_some() && config.deepen.is_some() {
+        return Err(ConfigError::Validation {
+            project_name: config.project.clone(),
+            message: "depth and deepen cannot both be set".to_string(),
+        });
+    }
+    if let Some(paths) = &config.submodule_paths {
+        if paths.is_empty() || paths.iter().any(|path| path.trim().is_empty()) {
+            return Err(ConfigError::Validation {
+                project_name: config.project.clone(),
+                message: "submodule_paths must list at least one non-empty path".to_string(),
+            });
+        }
+        if config.init_submodules == Some(false) {
+            return Err(ConfigError::Validation {
+                project_name: config.project.clone(),
+                message: "submodule_paths cannot be used with init_submodules set to false"
+                    .to_string(),
+            });
+        }
+    }
+    if config
+        .checkout_tag
+        .as_deref()
+        .is_some_and(|tag| tag.trim().is_empty())
+    {
+        return Err(ConfigError::Validation {
+            project_name: config.project.clone(),
+            message: "checkout_tag cannot be empty".to_string(),
+        });
+    }
+    if config.checkout_tag.is_some() && config.pull_branches.is_some() {
+        return Err(ConfigError::Validation {
+            project_name: config.project.clone(),
+            message: "checkout_tag and pull_branches cannot both be set".to_string(),
+        });
+    }
+    if let Some(branch) = &config.single_branch {
+        let problem = if branch.is_empty() || branch.chars().any(char::is_whitespace) {
+            Some("single_branch must be a branch name without whitespace".to_string())
+        } else if config.checkout_tag.is_some() {
+            Some("single_branch and checkout_tag cannot both be set".to_string())
+        } else if config.is_bare() {
+            Some("single_branch cannot be combined with bare or mirror".to_string())
+        } else if config
+            .pull_branches
+            .iter()
+            .flatten()
+            .any(|entry| entry != branch)
+        {
+            Some(format!(
+                "pull_branches can only list '{}', the single_branch",
+                branch
+            ))
+        } else {
+            None
+        };
+        if let Some(message) = problem {
+            return Err(ConfigError::Validation {
+                project_name: config.project.clone(),
+                message,
+            });
+        }
+    }
+    if config.bare.unwrap_or(false) && config.mirror.unwrap_or(false) {
+        return Err(ConfigError::Validation {
+            project_name: config.project.clone(),
+            message: "bare and mirror cannot both be set".to_string(),
+        });
+    }
+    if config.is_bare() {
+        let conflicting = [
+            ("pull_branches", config.pull_branches.is_some()),
+            ("checkout_tag", config.checkout_tag.is_some()),
+            ("clean_worktree", config.clean_worktree.unwrap_or(false)),
+            (
+                "reset_on_conflict",
+                config.reset_on_conflict.unwrap_or(false),
+            ),
+            ("fetch_only", config.fetch_only.unwrap_or(false)),
+            ("submodule_paths", config.submodule_paths.is_some()),
+            ("init_submodules", config.init_submodules.unwrap_or(false)),
+        ];
+        if let Some((field, _)) = conflicting.iter().find(|(_, set)| *set) {
+            return Err(ConfigError::Validation {
+                project_name: config.project.clone(),
+                message: format!(
+                    "bare and mirror clones have no work tree and cannot be combined with {}",
+                    field
+                ),
+            });
+        }
+    }
+    if config.fetch_only.unwrap_or(false) {
+        let conflicting = [
+            ("checkout_tag", config.checkout_tag.is_some()),
+            ("clean_worktree", config.clean_worktree.unwrap_or(false)),
+            (
+                "reset_on_conflict",
+                config.reset_on_conflict.unwrap_or(false),
+            ),
+        ];
+        if let Some((field, _)) = conflicting.iter().find(|(_, set)| *set) {
+            return Err(ConfigError::Validation {
+                project_name: config.project.clone(),
+                message: format!(
+                    "fetch_only leaves the work tree alone and cannot be combined with {}",
+                    field
+                ),
+            });
+        }
+    }
+    if config
+        .init_branch
+        .as_deref()
+        .is_some_and(|branch| branch.trim().is_empty())
+    {
+        return Err(ConfigError::Validation {
+            project_name: config.project.clone(),
+            message: "init_branch cannot be empty".to_string(),
+        });
+    }
+    if config
+        .expect_branch
+        .as_deref()
+        .is_some_and(|branch| branch.trim().is_empty())
+    {
+        return Err(ConfigError::Validation {
+            project_name: config.project.clone(),
+            message: "expect_branch cannot be empty".to_string(),
+        });
+    }
+    if let Some(commit) = &config.expect_commit {
+        if commit.len() < 4 || !commit.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ConfigError::Validation {
+                project_name: config.project.clone(),
+                message: format!(
+                    "expect_commit '{}' must be a commit hash of at least 4 hex digits",
+                    commit
+                ),
+            });
+        }
+    }
+    if let Some(key_path) = config.ssh_key_path() {
+        if !key_path.is_file() {
+            return Err(ConfigError::Validation {
+                project_name: config.project.clone(),
+                message: format!(
+                    "ssh_key '{}' does not exist or is not a file",
+                    key_path.display()
+                ),
+            });
+        }
+    }
+    if let Some(reference_path) = config.reference_path() {
+        if !is_git_repo(&reference_path) {
+            return Err(ConfigError::Validation {
+                project_name: config.project.clone(),
+                message: format!(
+                    "Reference '{}' does not exist or is not a git repository",
+                    reference_path.display()
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Describes why `url` cannot be a git remote, if it obviously cannot.
+/// Accepts `scheme://host/...` URLs, the scp-like `[user@]host:path` form and
+/// local paths; unknown schemes are allowed so remote helpers keep working.
+fn url_format_problem(url: &str) -> Option<&'static str> {
+    if url.chars().any(char::is_whitespace) {
+        return Some("contains whitespace");
+    }
+    if let Some((scheme, rest)) = url.split_once("://") {
+        if scheme.is_empty()
+            || !scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        {
+            return Some("has an invalid scheme");
+        }
+        if scheme == "file" {
+            return None;
+        }
+        let authority = rest.split('/').next().unwrap_or_default();
+        let host = authority.rsplit('@').next().unwrap_or_default();
+        if host.is_empty() || host.starts_with(':') {
+            return Some("has no host");
+        }
+        return None;
+    }
+    // `host:path` only counts as scp-like when no `/` comes before the colon;
+    // otherwise git treats the value as a local path.
+    if let Some((target, path)) = url.split_once(':') {
+        if !target.contains('/') {
+            let host = target.rsplit('@').next().unwrap_or_default();
+            if host.is_empty() {
+                return Some("has no host");
+            }
+            if path.is_empty() {
+                return Some("has no repository path");
+            }
+        }
+    }
+    None
+}
+
+/// Why `proxy` is not something git's `http.proxy` accepts:
+/// `[scheme://][user:password@]host[:port]` with an `http`, `https` or
+/// `socks` scheme.
+fn proxy_format_problem(proxy: &str) -> Option<&'static str> {
+    if proxy.chars().any(char::is_whitespace) {
+        return Some("contains whitespace");
+    }
+    let rest = match proxy.split_once("://") {
+        Some((scheme, rest)) => {
+            if !matches!(
+                scheme,
+                "http" | "https" | "socks" | "socks4" | "socks4a" | "socks5" | "socks5h"
+            ) {
+                return Some("has an unsupported scheme; expected http, https or socks5");
+            }
+            rest
+        }
+        None => proxy,
+    };
+    let authority = rest.strip_suffix('/').unwrap_or(rest);
+    if authority.contains('/') {
+        return Some("must not contain a path");
+    }
+    let host_port = authority.rsplit('@').next().unwrap_or_default();
+    // IPv6 hosts are bracketed: `[::1]:3128`.
+    let (host, port) = match host_port.strip_prefix('[') {
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some((host, "")) => (host, None),
+            Some((host, port)) => (host, Some(port.strip_prefix(':').unwrap_or(port))),
+            None => return Some("has an unclosed '['"),
+        },
+        None => match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (host_port, None),
+        },
+    };
+    if host.is_empty() {
+        return Some("has no host");
+    }
+    if port.is_some_and(|port| port.parse::<u16>().is_err()) {
+        return Some("has an invalid port");
+    }
+    None
+}
+
+fn validate_success_exit_codes(scope: &str, codes: &[i32]) -> Result<(), ConfigError> {
+    if let Some(code) = codes.iter().find(|code| !(1..=255).contains(*code)) {
+        return Err(ConfigError::Validation {
+            project_name: scope.to_string(),
+            message: format!(
+                "success_exit_codes entry {} is out of range; expected 1-255 (0 is always a success)",
+                code
+            ),
+        });
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(fields: Value) -> ProjectConfig {
+        let mut value = serde_json::json!({
+            "project": "api",
+            "url": "https://example.com/api.git",
+            "path": "api",
+        });
+        value
+            .as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().unwrap().clone());
+        serde_json::from_value(value).expect("minimal project config")
+    }
+
+    fn global_default(branches: &[&str]) -> GlobalConfig {
+        GlobalConfig {
+            default_pull_branches: Some(branches.iter().map(|b| b.to_string()).collect()),
+            ..GlobalConfig::default()
+        }
+    }
+
+    fn pull_branches<'a>(
+        config: &'a ProjectConfig,
+        global_config: &'a GlobalConfig,
+    ) -> Option<Vec<&'a str>> {
+        config
+            .effective_pull_branches(global_config)
+            .map(|branches| branches.iter().map(String::as_str).collect())
+    }
+
+    #[test]
+    fn cli_branch_overrides_project_and_global() {
+        let global_config = global_default(&["main"]);
+        let mut config = project(serde_json::json!({ "pull_branches": ["develop", "release"] }));
+        config.override_pull_branches("hotfix");
+        assert_eq!(pull_branches(&config, &global_config), Some(vec!["hotfix"]));
+
+        let mut config = project(serde_json::json!({}));
+        config.override_pull_branches("hotfix");
+        assert_eq!(pull_branches(&config, &global_config), Some(vec!["hotfix"]));
+    }
+
+    #[test]
+    fn cli_branch_leaves_tagged_projects_alone() {
+        let global_config = global_default(&["main"]);
+        let mut config = project(serde_json::json!({ "checkout_tag": "v1.2.3" }));
+        config.override_pull_branches("hotfix");
+        assert_eq!(pull_branches(&config, &global_config), None);
+    }
+
+    #[test]
+    fn project_branches_override_global_default() {
+        let global_config = global_default(&["main"]);
+        let config = project(serde_json::json!({ "pull_branches": ["develop"] }));
+        assert_eq!(
+            pull_branches(&config, &global_config),
+            Some(vec!["develop"])
+        );
+
+        let config = project(serde_json::json!({ "single_branch": "stable" }));
+        assert_eq!(pull_branches(&config, &global_config), Some(vec!["stable"]));
+    }
+
+    #[test]
+    fn empty_project_branches_still_override_global_default() {
+        let global_config = global_default(&["main"]);
+        let config = project(serde_json::json!({ "pull_branches": [] }));
+        assert_eq!(pull_branches(&config, &global_config), Some(vec![]));
+    }
+
+    #[test]
+    fn global_default_applies_when_project_sets_nothing() {
+        let global_config = global_default(&["main", "develop"]);
+        let config = project(serde_json::json!({}));
+        assert_eq!(
+            pull_branches(&config, &global_config),
+            Some(vec!["main", "develop"])
+        );
+    }
+
+    #[test]
+    fn current_branch_without_any_setting() {
+        let config = project(serde_json::json!({}));
+        assert_eq!(pull_branches(&config, &GlobalConfig::default()), None);
+    }
+
+    #[test]
+    fn tagged_and_bare_projects_never_inherit_global_default() {
+        let global_config = global_default(&["main"]);
+        let config = project(serde_json::json!({ "checkout_tag": "v1.2.3" }));
+        assert_eq!(pull_branches(&config, &global_config), None);
+        let config = project(serde_json::json!({ "bare": true }));
+        assert_eq!(pull_branches(&config, &global_config), None);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::logger::LogLevel;
+use console::{style, Term};
+use std::env;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Columns taken by the spinner, elapsed time, bar and counters of the
+/// overall progress bar template.
+const PROGRESS_BAR_RESERVED_WIDTH: usize = 75;
+const MIN_PATH_WIDTH: usize = 20;
+const FALLBACK_TERMINAL_WIDTH: usize = 120;
+const ELLIPSIS: char = '…';
+
+/// Set by `--print-paths`, whose output must be the only thing on stdout.
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// Whether [`print_level_status`] colors its lines.
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+pub fn set_stdout_reserved(reserved: bool) {
+    STDOUT_RESERVED.store(reserved, Ordering::Relaxed);
+}
+
+/// Enables colored status lines unless `--no-color` or `NO_COLOR` is set, or
+/// stderr is not a terminal.
+pub fn set_color(no_color: bool) {
+    let no_color_env = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let enabled = !no_color && !no_color_env && console::colors_enabled_stderr();
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+/// Prints a human-readable status line to stdout, or to stderr while stdout
+/// is reserved for machine-readable output.
+pub fn print_status(line: &str) {
+    if STDOUT_RESERVED.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Prints a status line to stderr in the color of `level`: green for
+/// success, red for errors, yellow for warnings and uncolored otherwise.
+pub fn print_level_status(level: LogLevel, line: &str) {
+    let styled = style(line).force_styling(COLOR.load(Ordering::Relaxed));
+    let styled = match level {
+        LogLevel::Success => styled.green(),
+        LogLevel::Error => styled.red(),
+        LogLevel::Warning => styled.yellow(),
+        LogLevel::Info | LogLevel::Trace => styled,
+    };
+    eprintln!("{}", styled);
+}
+
+/// Shortens `path` with a middle ellipsis so progress messages containing it
+/// stay on one line. Only meant for the progress bar; logs keep the full path.
+pub fn shorten_path(path: &Path) -> String {
+    shorten_middle(&path.display().to_string(), path_width_budget())
+}
+
+/// Same as [`shorten_path`] for URLs and other long location strings.
+pub fn shorten_location(location: &str) -> String {
+    shorten_middle(location, path_width_budget())
+}
+
+fn path_width_budget() -> usize {
+    let terminal_width = Term::stderr()
+        .size_checked()
+        .map(|(_, columns)| columns as usize)
+        .unwrap_or(FALLBACK_TERMINAL_WIDTH);
+    // Messages usually carry a path plus some surrounding text, so give the
+    // path half of what is left after the bar itself.
+    (terminal_width.saturating_sub(PROGRESS_BAR_RESERVED_WIDTH) / 2).max(MIN_PATH_WIDTH)
+}
+
+fn shorten_middle(text: &str, max_width: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= max_width || max_width < 3 {
+        return text.to_string();
+    }
+
+    // Favour the tail: the last path components identify the project.
+    let keep = max_width - 1;
+    let head_len = keep / 3;
+    let tail_len = keep - head_len;
+    let head: String = chars[..head_len].iter().collect();
+    let tail: String = chars[chars.len() - tail_len..].iter().collect();
+    format!("{}{}{}", head, ELLIPSIS, tail)
+}
@@ -7,8 +7,28 @@ pub enum ConfigError {
     ReadFile(PathBuf, #[source] std::io::Error),
     #[error("Failed to parse config file: {0}")]
     Parse(#[from] serde_json::Error),
+    #[error("Failed to parse YAML config file: {0}")]
+    ParseYaml(#[from] serde_yaml::Error),
+    #[error("Failed to parse TOML config file: {0}")]
+    ParseToml(#[from] toml::de::Error),
+    #[error("Config file is neither valid JSON ({json}) nor valid YAML ({yaml})")]
+    ParseUnknownFormat {
+        json: serde_json::Error,
+        yaml: serde_yaml::Error,
+    },
     #[error("Validation error for project '{project_name}': {message}")]
-    Validation { project_name: String, message: String },
+    Validation {
+        project_name: String,
+        message: String,
+    },
+    #[error("Project name '{0}' is used by more than one project; names must be unique.")]
+    DuplicateName(String),
+    #[error("Projects '{first}' and '{second}' both resolve to the path '{}'.", path.display())]
+    DuplicatePath {
+        first: String,
+        second: String,
+        path: PathBuf,
+    },
     #[error("Configuration file '{0}' not found.")]
     NotFound(PathBuf),
     #[error("Configuration file is empty or contains no projects.")]
@@ -31,14 +51,48 @@ pub enum GitError {
         stdout: String,
         stderr: String,
     },
+    #[error("Git ran out of disk space for '{project_name}' while writing to '{}'. Command: '{command}'. Free up space and rerun.", path.display())]
+    DiskFull {
+        project_name: String,
+        command: String,
+        path: PathBuf,
+    },
+    #[error("Commit signature verification failed for '{project_name}': {message}")]
+    SignatureVerificationFailed {
+        project_name: String,
+        message: String,
+    },
+    #[error("Git command for '{project_name}' timed out after {secs}s and was killed. Command: '{command}'.")]
+    Timeout {
+        project_name: String,
+        command: String,
+        secs: u64,
+    },
     #[error("Failed to get current branch for '{project_name}': {message}")]
-    BranchInfoError { project_name: String, message: String },
+    BranchInfoError {
+        project_name: String,
+        message: String,
+    },
+    #[error("The clone of '{project_name}' at '{}' has no remote named '{remote}'. Add it with 'git remote add' or fix the remote setting.", path.display())]
+    MissingRemote {
+        project_name: String,
+        remote: String,
+        path: PathBuf,
+    },
+    #[error("Git command for '{project_name}' is blocked by '{}'. Command: '{command}'. If no other git process is running, delete the lock file or rerun with --remove-stale-locks.", lock_path.display())]
+    IndexLocked {
+        project_name: String,
+        command: String,
+        lock_path: PathBuf,
+    },
 }
 
 #[derive(Error, Debug)]
 pub enum ProjectError {
     // Removed PathResolutionError variant
-    #[error("Project '{project_name}': Failed to create parent directories for '{path}': {source}")]
+    #[error(
+        "Project '{project_name}': Failed to create parent directories for '{path}': {source}"
+    )]
     CreateDirs {
         project_name: String,
         path: PathBuf,
@@ -51,8 +105,54 @@ pub enum ProjectError {
         #[source]
         source: GitError,
     },
-    #[error("Project '{project_name}': Non-Git directory found at '{path}', or clone failed earlier.")]
+    #[error(
+        "Project '{project_name}': Non-Git directory found at '{path}', or clone failed earlier."
+    )]
     NotGitRepository { project_name: String, path: PathBuf },
+    #[error("Project '{project_name}': Refusing to use '{path}' because {reason}.")]
+    UnsafePath {
+        project_name: String,
+        path: PathBuf,
+        reason: String,
+    },
+    #[error("Project '{project_name}': Hook '{command}' failed: {message}")]
+    HookFailed {
+        project_name: String,
+        command: String,
+        message: String,
+    },
+    #[error(
+        "Project '{project_name}': Remote '{url}' did not answer the --preflight check: {reason}"
+    )]
+    Unreachable {
+        project_name: String,
+        url: String,
+        reason: String,
+    },
+    #[error("Project '{project_name}': Post-update check failed: {message}")]
+    ExpectationFailed {
+        project_name: String,
+        message: String,
+    },
+    #[error("Project '{project_name}': Archive import failed: {message}")]
+    Archive {
+        project_name: String,
+        message: String,
+    },
+    #[error("Project '{project_name}': Failed to lock '{path}' for this run: {source}")]
+    Lock {
+        project_name: String,
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Project '{project_name}': Failed to back up '{path}' before re-cloning: {source}")]
+    Backup {
+        project_name: String,
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -67,4 +167,16 @@ pub enum AppError {
     CtrlCSetup(#[from] ctrlc::Error),
     #[error("Failed to get current working directory: {0}")]
     CurrentDir(#[source] std::io::Error),
-}
\ No newline at end of file
+    #[error("Failed to open report file '{0}': {1}")]
+    Report(PathBuf, #[source] std::io::Error),
+    #[error("Invalid arguments: {0}. Run with --help for usage.")]
+    Usage(String),
+    #[error("Cannot use log file '{0}': {1}")]
+    LogFile(String, #[source] std::io::Error),
+    #[error("Cannot write starter config '{}': {}", .0.display(), .1)]
+    InitFile(PathBuf, #[source] std::io::Error),
+    #[error("Cannot log to syslog: {0}")]
+    Syslog(#[source] std::io::Error),
+    #[error("git could not be run; is it installed and on PATH? ({0})")]
+    GitMissing(#[source] std::io::Error),
+}
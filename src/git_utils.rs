@@ -1,76 +1,971 @@
+use crate::config::{ProjectConfig, RetryJitter};
+use crate::display::{print_level_status, print_status, shorten_location, shorten_path};
 use crate::error::GitError;
-use crate::logger::{log_to_file, LogLevel};
+use crate::logger::{log_to_file, log_trace, truncate_output, LogLevel};
+use crate::redact::{redact_text, redact_url};
 use indicatif::ProgressBar;
-use std::path::Path;
-use std::process::{Command, Stdio};
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
+/// Git config key recording that a checkout was imported from a tarball
+/// rather than cloned, so it has no upstream history to pull.
+pub const ARCHIVE_SOURCE_CONFIG_KEY: &str = "project-updater.archive-source";
+
+/// A lock file older than this is assumed to be left behind by a git process
+/// that died, rather than held by one that is still running.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Whether `--remove-stale-locks` allows deleting an old `index.lock`.
+static REMOVE_STALE_LOCKS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_remove_stale_locks(enabled: bool) {
+    REMOVE_STALE_LOCKS.store(enabled, Ordering::Relaxed);
+}
+
+/// Characters kept by [`abbreviate_hash`]; set from `hash_length`.
+static HASH_LENGTH: AtomicUsize = AtomicUsize::new(7);
+
+pub fn set_hash_length(length: usize) {
+    HASH_LENGTH.store(length, Ordering::Relaxed);
+}
+
+/// Shortens a full commit hash to the configured `hash_length` for logs and
+/// reports.
+pub fn abbreviate_hash(hash: &str) -> String {
+    hash.chars()
+        .take(HASH_LENGTH.load(Ordering::Relaxed))
+        .collect()
+}
+
+/// Extra attempts for network failures (`--retries`), the delay before the
+/// first of them (`retry_delay_ms`) and how it is randomized (`retry_jitter`).
+static RETRIES: AtomicU32 = AtomicU32::new(0);
+static RETRY_DELAY_MS: AtomicU64 = AtomicU64::new(DEFAULT_RETRY_DELAY_MS);
+static RETRY_JITTER: Mutex<RetryJitter> = Mutex::new(RetryJitter::Equal);
+
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 1000;
+
+pub fn set_retry_policy(retries: u32, delay_ms: u64, jitter: RetryJitter) {
+    RETRIES.store(retries, Ordering::Relaxed);
+    RETRY_DELAY_MS.store(delay_ms, Ordering::Relaxed);
+    *RETRY_JITTER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = jitter;
+}
+
+/// Seconds a git command may run before it is killed (`command_timeout_secs`);
+/// 0 means no limit.
+static COMMAND_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_command_timeout(secs: u64) {
+    COMMAND_TIMEOUT_SECS.store(secs, Ordering::Relaxed);
+}
+
+/// Token file from `credentials_file`, offered to git for HTTPS remotes.
+static CREDENTIALS_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+pub fn set_credentials_file(path: PathBuf) {
+    *CREDENTIALS_FILE.lock().unwrap_or_else(|e| e.into_inner()) = Some(path);
+}
+
+/// Proxy from the global `proxy`, passed to git as `http.proxy`.
+static PROXY: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn set_proxy(proxy: String) {
+    *PROXY.lock().unwrap_or_else(|e| e.into_inner()) = Some(proxy);
+}
+
+/// Upper bound for the interval at which a timed command is polled.
+const MAX_TIMEOUT_POLL: Duration = Duration::from_millis(50);
+
+/// Whether `--dry-run` replaces git commands that change a repository with a
+/// printed plan.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Git subcommands that write to a repository or its working tree.
+fn is_mutating(args: &[String]) -> bool {
+    match args.first().map(String::as_str) {
+        Some(
+            "clone" | "checkout" | "pull" | "fetch" | "reset" | "merge" | "clean" | "stash"
+            | "symbolic-ref" | "gc",
+        ) => true,
+        Some("submodule") => args.get(1).is_some_and(|sub| sub == "update"),
+        Some("remote") => args.get(1).is_some_and(|sub| {
+            matches!(
+                sub.as_str(),
+                "update" | "prune" | "set-branches" | "set-url"
+            )
+        }),
+        _ => false,
+    }
+}
+
+/// Logs and prints an action `--dry-run` skipped, above the progress bars
+/// when they are drawn.
+pub fn announce_dry_run(progress_bar: &ProgressBar, action: &str) {
+    let line = format!("[dry-run] {}", action);
+    log_to_file(LogLevel::Info, &line);
+    if progress_bar.is_hidden() {
+        print_status(&line);
+    } else {
+        progress_bar.println(&line);
+    }
+}
+
+/// Remote name used when a project does not set `remote`.
+pub const DEFAULT_REMOTE: &str = "origin";
+
+/// Per-project settings shared by every git invocation for that project.
+pub struct GitContext<'a> {
+    pub project_name: &'a str,
+    pub progress_bar: &'a ProgressBar,
+    /// Non-zero exit codes accepted as success in addition to 0.
+    pub success_exit_codes: &'a [i32],
+    /// Private key ssh should use instead of its defaults (`ssh_key`).
+    pub ssh_key: Option<&'a Path>,
+    /// Remote the project is fetched from (`remote`, `origin` by default).
+    pub remote: &'a str,
+}
+
+/// How [`clone_repo`] clones a project, resolved from its config.
+pub struct CloneOptions<'a> {
+    /// History depth; `None` or 0 clones the full history.
+    pub depth: Option<u32>,
+    /// Local repository to borrow objects from (`--reference`).
+    pub reference: Option<PathBuf>,
+    /// Copy borrowed objects so the clone survives the reference going away.
+    pub dissociate: bool,
+    /// Submodules cloned along with the project; none when empty.
+    pub submodule_paths: &'a [String],
+    /// Name given to the cloned-from remote instead of `origin`.
+    pub remote: &'a str,
+    /// Clone without a work tree (`--bare`).
+    pub bare: bool,
+    /// Bare clone mapping every remote ref one to one (`--mirror`).
+    pub mirror: bool,
+    /// Clone only this branch (`--branch <name> --single-branch`).
+    pub single_branch: Option<&'a str>,
+}
+
+impl<'a> CloneOptions<'a> {
+    pub fn from_config(config: &'a ProjectConfig) -> Self {
+        CloneOptions {
+            depth: config.depth,
+            reference: config.reference_path(),
+            dissociate: config.dissociate.unwrap_or(true),
+            submodule_paths: config.submodule_paths.as_deref().unwrap_or_default(),
+            remote: config.remote_name(),
+            bare: config.bare.unwrap_or(false),
+            mirror: config.mirror.unwrap_or(false),
+            single_branch: config.single_branch.as_deref(),
+        }
+    }
+
+    /// `git clone` arguments for cloning `repo_url` into `target_path`.
+    pub fn args(&self, repo_url: &str, target_path: &Path) -> Vec<String> {
+        let mut args = git_args(&["clone"]);
+        if let Some(depth) = self.depth.filter(|depth| *depth > 0) {
+            args.push(format!("--depth={}", depth));
+        }
+        for path in self.submodule_paths {
+            args.push(format!("--recurse-submodules={}", path));
+        }
+        if self.mirror {
+            args.push("--mirror".to_string());
+        } else if self.bare {
+            args.push("--bare".to_string());
+        }
+        if let Some(branch) = self.single_branch {
+            args.push(format!("--branch={}", branch));
+            args.push("--single-branch".to_string());
+        }
+        if self.remote != DEFAULT_REMOTE {
+            args.push(format!("--origin={}", self.remote));
+        }
+        if let Some(reference_path) = &self.reference {
+            args.push("--reference".to_string());
+            args.push(reference_path.display().to_string());
+            if self.dissociate {
+                args.push("--dissociate".to_string());
+            }
+        }
+        args.push(repo_url.to_string());
+        args.push(target_path.display().to_string());
+        args
+    }
+}
+
+/// What [`pull_branch_updates`] pulls. Pulls never pass `--depth`, even for
+/// shallow clones: re-grafting the fetched tip makes it unrelated to the
+/// local branch.
+pub struct PullOptions<'a> {
+    /// Remote `branch` is pulled from.
+    pub remote: &'a str,
+    /// Branch to pull from `remote`; `None` pulls the current branch from its
+    /// upstream.
+    pub branch: Option<&'a str>,
+    /// Fetch `branch` from `remote` (or every remote when `branch` is `None`)
+    /// instead of pulling, leaving the work tree alone.
+    pub fetch_only: bool,
+}
+
+impl PullOptions<'_> {
+    /// `git pull` (or `git fetch`) arguments for these options.
+    pub fn args(&self) -> Vec<String> {
+        if self.fetch_only {
+            return match self.branch {
+                Some(branch) => git_args(&["fetch", self.remote, branch]),
+                None => git_args(&["fetch", "--all"]),
+            };
+        }
+        let mut args = git_args(&["pull"]);
+        if let Some(branch) = self.branch {
+            args.push(self.remote.to_string());
+            args.push(branch.to_string());
+        }
+        args
+    }
+}
+
+/// Runs `git <args>` (in `repo_path` when given) and returns its captured
+/// output regardless of exit status. Only failing to spawn git is an error.
+/// Under `--dry-run`, commands that would change the repository are only
+/// announced and report success with no output.
+fn run_git(
+    ctx: &GitContext,
+    repo_path: Option<&Path>,
+    args: &[String],
+) -> Result<Output, GitError> {
+    if is_dry_run() && is_mutating(args) {
+        let location = repo_path
+            .map(|path| format!(" (in '{}')", path.display()))
+            .unwrap_or_default();
+        announce_dry_run(
+            ctx.progress_bar,
+            &format!(
+                "{}: would run '{}'{}",
+                ctx.project_name,
+                command_display(args),
+                location
+            ),
+        );
+        return Ok(Output {
+            status: ExitStatus::default(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        });
+    }
+    let mut command = Command::new("git");
+    if let Some(repo_path) = repo_path {
+        command.current_dir(repo_path);
+    }
+    if let Some(credentials_file) = CREDENTIALS_FILE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_deref()
+    {
+        // Kept out of `args`, which follow these, so traces and errors show
+        // the command as written. The empty value drops any helper from the
+        // user's own git config.
+        command
+            .args(["-c", "credential.helper="])
+            .arg("-c")
+            .arg(format!(
+                "credential.helper={}",
+                credential_helper(credentials_file)
+            ));
+    }
+    if let Some(proxy) = PROXY.lock().unwrap_or_else(|e| e.into_inner()).as_deref() {
+        // Covers https:// remotes too, and may hold credentials, so it is
+        // kept out of `args` as well.
+        command.arg("-c").arg(format!("http.proxy={}", proxy));
+    }
+    // Credential prompts would block forever behind the progress bars.
+    command
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(ssh_key) = ctx.ssh_key {
+        command.env("GIT_SSH_COMMAND", ssh_command(ssh_key));
+    }
+    let timeout_secs = COMMAND_TIMEOUT_SECS.load(Ordering::Relaxed);
+    // `--progress` output is shown on the project's bar as it arrives.
+    let progress_bar = args
+        .iter()
+        .any(|arg| arg == "--progress")
+        .then(|| ctx.progress_bar.clone());
+    let started = Instant::now();
+    let output = if timeout_secs > 0 || progress_bar.is_some() {
+        let timeout = (timeout_secs > 0).then(|| Duration::from_secs(timeout_secs));
+        command
+            .spawn()
+            .and_then(|child| wait_with_timeout(child, timeout, progress_bar, ctx.project_name))
+    } else {
+        command.output().map(Some)
+    };
+    log_trace(
+        &command_display(args),
+        repo_path,
+        output
+            .as_ref()
+            .ok()
+            .and_then(Option::as_ref)
+            .and_then(|output| output.status.code()),
+        started.elapsed(),
+    );
+    match output {
+        Ok(Some(output)) => Ok(output),
+        Ok(None) => {
+            log_to_file(
+                LogLevel::Error,
+                &format!(
+                    "Project '{}': '{}' did not finish within {}s; killed it.",
+                    ctx.project_name,
+                    command_display(args),
+                    timeout_secs
+                ),
+            );
+            Err(GitError::Timeout {
+                project_name: ctx.project_name.to_string(),
+                command: command_display(args),
+                secs: timeout_secs,
+            })
+        }
+        Err(e) => Err(GitError::CommandExecution {
+            project_name: ctx.project_name.to_string(),
+            command: command_display(args),
+            source: e,
+        }),
+    }
+}
+
+/// Inline credential helper answering `get` from `credentials_file`. Its
+/// first line is either a token, sent with the username `x-access-token`
+/// (accepted by GitHub; other hosts ignore the name when a token is used),
+/// or `username:token`. The file is read by the helper, so the token never
+/// appears in a command line or log.
+fn credential_helper(credentials_file: &Path) -> String {
+    let quoted = credentials_file
+        .display()
+        .to_string()
+        .replace('\'', r"'\''");
+    format!(
+        "!f() {{ test \"$1\" = get || return 0; line=$(head -n 1 '{}' | tr -d '\\r'); \
+         case \"$line\" in *:*) echo \"username=${{line%%:*}}\"; echo \"password=${{line#*:}}\";; \
+         *) echo username=x-access-token; echo \"password=$line\";; esac; }}; f",
+        quoted
+    )
+}
+
+/// `GIT_SSH_COMMAND` that makes ssh offer only `key`. git runs it through
+/// the shell, so the path is single-quoted.
+fn ssh_command(key: &Path) -> String {
+    let quoted = key.display().to_string().replace('\'', r"'\''");
+    format!("ssh -i '{}' -o IdentitiesOnly=yes", quoted)
+}
+
+/// Like [`Child::wait_with_output`], but kills the child and returns `None`
+/// once `timeout` (if any) has passed. With `progress_bar`, git's progress
+/// lines on stderr update the bar as they arrive instead of being captured.
+fn wait_with_timeout(
+    mut child: Child,
+    timeout: Option<Duration>,
+    progress_bar: Option<ProgressBar>,
+    project_name: &str,
+) -> io::Result<Option<Output>> {
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let stdout_reader = thread::spawn(move || read_pipe(stdout));
+    let project_name = project_name.to_string();
+    let stderr_reader = thread::spawn(move || match progress_bar {
+        Some(progress_bar) => read_progress_pipe(stderr, &progress_bar, &project_name),
+        None => read_pipe(stderr),
+    });
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut poll_interval = Duration::from_millis(1);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let now = Instant::now();
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            let _ = child.kill();
+            let _ = child.wait();
+            // The readers are left to finish on their own: a process git
+            // started, such as ssh, may still hold the pipes open.
+            return Ok(None);
+        }
+        let remaining = deadline.map_or(MAX_TIMEOUT_POLL, |deadline| deadline - now);
+        thread::sleep(poll_interval.min(remaining));
+        poll_interval = (poll_interval * 2).min(MAX_TIMEOUT_POLL);
+    };
+
+    Ok(Some(Output {
+        status,
+        stdout: stdout_reader.join().unwrap_or_default(),
+        stderr: stderr_reader.join().unwrap_or_default(),
+    }))
+}
+
+fn read_pipe(pipe: Option<impl Read>) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    if let Some(mut pipe) = pipe {
+        let _ = pipe.read_to_end(&mut buffer);
+    }
+    buffer
+}
+
+/// Reads git's stderr, showing each progress line (`Receiving objects:  42%
+/// (...)`) as the bar's message. git redraws those with `\r`, so only the
+/// other lines are kept for the returned output.
+fn read_progress_pipe(
+    pipe: Option<impl Read>,
+    progress_bar: &ProgressBar,
+    project_name: &str,
+) -> Vec<u8> {
+    let mut kept = Vec::new();
+    let Some(mut pipe) = pipe else {
+        return kept;
+    };
+    let mut line = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let read = match pipe.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        for &byte in &chunk[..read] {
+            if byte != b'\r' && byte != b'\n' {
+                line.push(byte);
+                continue;
+            }
+            match parse_git_progress(&String::from_utf8_lossy(&line)) {
+                Some((phase, percent)) => {
+                    progress_bar.set_message(format!("{} - {}: {}%", project_name, phase, percent));
+                }
+                None if !line.is_empty() => {
+                    kept.append(&mut line);
+                    kept.push(b'\n');
+                }
+                None => {}
+            }
+            line.clear();
+        }
+    }
+    kept.extend_from_slice(&line);
+    kept
+}
+
+/// Splits a git progress line such as `remote: Counting objects:  42% (42/100)`
+/// into its phase (`Counting objects`) and percentage.
+fn parse_git_progress(line: &str) -> Option<(String, u8)> {
+    let line = line.strip_prefix("remote: ").unwrap_or(line);
+    let (phase, rest) = line.rsplit_once(": ")?;
+    let (percent, _) = rest.trim_start().split_once('%')?;
+    Some((phase.to_string(), percent.parse().ok()?))
+}
+
+/// Like [`run_git`], but maps an unaccepted exit status to
+/// `GitError::CommandFailed`, `GitError::DiskFull` when git ran out of space,
+/// or `GitError::IndexLocked` when a leftover `index.lock` blocked the
+/// command. With `--remove-stale-locks` a lock older than [`STALE_LOCK_AGE`]
+/// is removed and the command retried once.
+fn run_git_checked(
+    ctx: &GitContext,
+    repo_path: Option<&Path>,
+    args: &[String],
+) -> Result<Output, GitError> {
+    let output = run_git(ctx, repo_path, args)?;
+    if is_accepted_status(ctx, output.status, args) {
+        return Ok(output);
+    }
+
+    let Some(lock_path) = index_lock_path(&output) else {
+        return Err(checked_failure(ctx, repo_path, args, &output));
+    };
+    if !(REMOVE_STALE_LOCKS.load(Ordering::Relaxed) && remove_stale_lock(ctx, &lock_path)) {
+        return Err(GitError::IndexLocked {
+            project_name: ctx.project_name.to_string(),
+            command: command_display(args),
+            lock_path,
+        });
+    }
+
+    let output = run_git(ctx, repo_path, args)?;
+    if is_accepted_status(ctx, output.status, args) {
+        Ok(output)
+    } else {
+        Err(checked_failure(ctx, repo_path, args, &output))
+    }
+}
+
+fn checked_failure(
+    ctx: &GitContext,
+    repo_path: Option<&Path>,
+    args: &[String],
+    output: &Output,
+) -> GitError {
+    if String::from_utf8_lossy(&output.stderr).contains("No space left on device") {
+        GitError::DiskFull {
+            project_name: ctx.project_name.to_string(),
+            command: command_display(args),
+            path: repo_path.unwrap_or(Path::new(".")).to_path_buf(),
+        }
+    } else {
+        command_failed(ctx, args, output)
+    }
+}
+
+/// Like [`run_git_checked`], but retries a command that failed with a
+/// transient network error up to `--retries` times, doubling the delay
+/// (starting at `retry_delay_ms`, randomized per `retry_jitter`) after each
+/// attempt. Only commands that are safe to repeat (see [`is_idempotent`])
+/// are retried.
+fn run_git_retrying(
+    ctx: &GitContext,
+    repo_path: Option<&Path>,
+    args: &[String],
+) -> Result<Output, GitError> {
+    let retries = RETRIES.load(Ordering::Relaxed);
+    let jitter = *RETRY_JITTER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut rng = JitterRng::from_entropy();
+    let mut attempt = 0;
+    loop {
+        let result = run_git_checked(ctx, repo_path, args);
+        let stderr = match &result {
+            Err(GitError::CommandFailed { stderr, .. }) if is_transient_failure(stderr) => stderr,
+            _ => return result,
+        };
+        if retries > 0 && !is_idempotent(args) {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': '{}' failed with a network error but is not retried, since it may have partially applied.",
+                    ctx.project_name,
+                    command_display(args)
+                ),
+            );
+        }
+        if attempt >= retries || !is_idempotent(args) {
+            return result;
+        }
+
+        attempt += 1;
+        let base_delay = Duration::from_millis(RETRY_DELAY_MS.load(Ordering::Relaxed));
+        let delay = retry_delay(base_delay, attempt, jitter, &mut rng);
+        log_to_file(
+            LogLevel::Warning,
+            &format!(
+                "Project '{}': '{}' failed with a network error (attempt {}/{}), retrying in {} ms: {}",
+                ctx.project_name,
+                command_display(args),
+                attempt,
+                retries + 1,
+                delay.as_millis(),
+                stderr.lines().last().unwrap_or_default()
+            ),
+        );
+        ctx.progress_bar.set_message(format!(
+            "{} - network error, retry {}/{} in {} ms",
+            ctx.project_name,
+            attempt,
+            retries,
+            delay.as_millis()
+        ));
+        thread::sleep(delay);
+    }
+}
+
+/// Whether repeating `git <args>` after a failure cannot duplicate its
+/// effect. Fetching is safe: a failed clone leaves nothing behind and a
+/// failed pull stops before merging. Commands that create commits or update
+/// a remote (`commit`, `push`, ...) are not, as the first attempt may have
+/// applied before the connection dropped.
+fn is_idempotent(args: &[String]) -> bool {
+    match args.first().map(String::as_str) {
+        Some("clone" | "fetch" | "pull" | "ls-remote") => true,
+        Some("remote") => args.get(1).is_some_and(|sub| sub == "update"),
+        _ => false,
+    }
+}
+
+/// Delay before retry number `attempt` (1-based): `base`, then doubling,
+/// with `jitter` drawn from `rng`.
+fn retry_delay(base: Duration, attempt: u32, jitter: RetryJitter, rng: &mut JitterRng) -> Duration {
+    let delay = base.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+    let delay_ms = u64::try_from(delay.as_millis()).unwrap_or(u64::MAX);
+    let random_up_to = |max_ms: u64, rng: &mut JitterRng| rng.next_u64() % max_ms.saturating_add(1);
+    match jitter {
+        RetryJitter::None => delay,
+        RetryJitter::Full => Duration::from_millis(random_up_to(delay_ms, rng)),
+        RetryJitter::Equal => {
+            let fixed_ms = delay_ms - delay_ms / 2;
+            Duration::from_millis(fixed_ms + random_up_to(delay_ms / 2, rng))
+        }
+    }
+}
+
+/// Small seedable generator (SplitMix64) for retry jitter; statistical
+/// quality beyond spreading out retries does not matter here.
+struct JitterRng(u64);
+
+impl JitterRng {
+    fn new(seed: u64) -> Self {
+        JitterRng(seed)
+    }
+
+    /// Seeded from the standard library's per-process random hash keys.
+    fn from_entropy() -> Self {
+        JitterRng::new(RandomState::new().build_hasher().finish())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Whether git's stderr points at a network hiccup worth retrying rather
+/// than a problem retrying cannot fix, such as rejected credentials.
+fn is_transient_failure(stderr: &str) -> bool {
+    const PERMANENT: &[&str] = &[
+        "Authentication failed",
+        "Permission denied",
+        "could not read Username",
+        "Repository not found",
+        "CONFLICT",
+    ];
+    const TRANSIENT: &[&str] = &[
+        "Could not resolve host",
+        "Temporary failure in name resolution",
+        "Connection timed out",
+        "Operation timed out",
+        "Connection reset",
+        "Connection refused",
+        "Failed to connect",
+        "early EOF",
+        "The remote end hung up unexpectedly",
+        "unexpected disconnect",
+        "RPC failed",
+    ];
+    !PERMANENT.iter().any(|pattern| stderr.contains(pattern))
+        && TRANSIENT.iter().any(|pattern| stderr.contains(pattern))
+}
+
+/// Extracts the lock file from git's "Unable to create '<path>': File exists."
+fn index_lock_path(output: &Output) -> Option<PathBuf> {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let (_, rest) = stderr.split_once("Unable to create '")?;
+    let (path, rest) = rest.split_once('\'')?;
+    (path.ends_with("index.lock") && rest.starts_with(": File exists")).then(|| PathBuf::from(path))
+}
+
+/// Deletes `lock_path` if it is older than [`STALE_LOCK_AGE`]. Returns whether
+/// the lock is gone and the command is worth retrying.
+fn remove_stale_lock(ctx: &GitContext, lock_path: &Path) -> bool {
+    let age = fs::metadata(lock_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok());
+    match age {
+        Some(age) if age >= STALE_LOCK_AGE => match fs::remove_file(lock_path) {
+            Ok(()) => {
+                log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "Project '{}': Removed stale lock '{}' ({} minutes old), retrying.",
+                        ctx.project_name,
+                        lock_path.display(),
+                        age.as_secs() / 60
+                    ),
+                );
+                true
+            }
+            Err(e) => {
+                log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "Project '{}': Could not remove stale lock '{}': {}",
+                        ctx.project_name,
+                        lock_path.display(),
+                        e
+                    ),
+                );
+                false
+            }
+        },
+        Some(_) => {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Lock '{}' is recent; another git process may still be running. Not removing it.",
+                    ctx.project_name,
+                    lock_path.display()
+                ),
+            );
+            false
+        }
+        None => false,
+    }
+}
+
+fn is_accepted_status(ctx: &GitContext, status: ExitStatus, args: &[String]) -> bool {
+    if status.success() {
+        return true;
+    }
+    match status.code() {
+        Some(code) if ctx.success_exit_codes.contains(&code) => {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': '{}' exited with code {}, accepted as success by success_exit_codes.",
+                    ctx.project_name,
+                    command_display(args),
+                    code
+                ),
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+fn command_failed(ctx: &GitContext, args: &[String], output: &Output) -> GitError {
+    GitError::CommandFailed {
+        project_name: ctx.project_name.to_string(),
+        command: command_display(args),
+        stdout: redact_text(&truncate_output(
+            String::from_utf8_lossy(&output.stdout).trim(),
+        )),
+        stderr: redact_text(&truncate_output(
+            String::from_utf8_lossy(&output.stderr).trim(),
+        )),
+    }
+}
+
+fn command_display(args: &[String]) -> String {
+    redact_text(&format!("git {}", args.join(" ")))
+}
+
+fn git_args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Checks that a `git` executable can be started at all.
+pub fn check_git_available() -> std::io::Result<()> {
+    Command::new("git")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|_| ())
+}
+
+/// Whether `path` holds a repository, either a work tree with `.git` or a
+/// bare repository with `HEAD`, `objects` and `refs` at the top.
 pub fn is_git_repo(path: &Path) -> bool {
     path.join(".git").is_dir()
+        || (path.join("HEAD").is_file()
+            && path.join("objects").is_dir()
+            && path.join("refs").is_dir())
 }
 
 pub fn clone_repo(
-    progress_bar: &ProgressBar,
-    project_name: &str,
+    ctx: &GitContext,
     repo_url: &str,
     target_path: &Path,
+    options: &CloneOptions,
 ) -> Result<(), GitError> {
-    let msg = format!(
+    let display_url = redact_url(repo_url);
+    ctx.progress_bar.set_message(format!(
         "Cloning '{}' from '{}' into '{}'...",
-        project_name,
-        repo_url,
-        target_path.display()
+        ctx.project_name,
+        shorten_location(&display_url),
+        shorten_path(target_path)
+    ));
+    log_to_file(
+        LogLevel::Info,
+        &format!(
+            "Cloning '{}' from '{}' into '{}'...",
+            ctx.project_name,
+            display_url,
+            target_path.display()
+        ),
     );
-    progress_bar.set_message(msg.clone());
-    log_to_file(LogLevel::Info, &msg);
 
-    let output = Command::new("git")
-        .arg("clone")
-        .arg(repo_url)
-        .arg(target_path)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|e| GitError::CommandExecution {
-            project_name: project_name.to_string(),
-            command: format!("git clone {} {}", repo_url, target_path.display()),
-            source: e,
-        })?;
+    if let Some(reference_path) = &options.reference {
+        if !options.dissociate {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Cloning with reference '{}' without --dissociate. The clone will break if the reference repository is removed.",
+                    ctx.project_name,
+                    reference_path.display()
+                ),
+            );
+        }
+    }
+    let mut clone_args = options.args(repo_url, target_path);
 
-    if output.status.success() {
-        let success_msg = format!("Successfully cloned '{}'.", project_name);
-        progress_bar.set_message(success_msg.clone());
-        log_to_file(LogLevel::Success, &success_msg);
+    let target_existed = target_path.exists();
+    if is_dry_run() {
+        run_git_checked(ctx, None, &clone_args)?;
+        return Ok(());
+    }
+    if !ctx.progress_bar.is_hidden() {
+        // git only reports progress to a terminal unless asked.
+        clone_args.insert(1, "--progress".to_string());
+    }
+    let result = run_git_retrying(ctx, None, &clone_args);
+    // A clone that ran out of space or was killed leaves a half-written
+    // checkout behind that later runs would mistake for a clone.
+    if matches!(
+        result,
+        Err(GitError::DiskFull { .. } | GitError::Timeout { .. })
+    ) && !target_existed
+        && target_path.exists()
+    {
+        if let Err(e) = fs::remove_dir_all(target_path) {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not remove partial clone '{}': {}",
+                    ctx.project_name,
+                    target_path.display(),
+                    e
+                ),
+            );
+        }
+    }
+    match result {
+        Ok(_) => {}
+        Err(GitError::DiskFull {
+            project_name,
+            command,
+            ..
+        }) => {
+            return Err(GitError::DiskFull {
+                project_name,
+                command,
+                path: target_path.to_path_buf(),
+            });
+        }
+        Err(e) => return Err(e),
+    }
+
+    let success_msg = format!("Successfully cloned '{}'.", ctx.project_name);
+    ctx.progress_bar.set_message(success_msg.clone());
+    log_to_file(LogLevel::Success, &success_msg);
+    Ok(())
+}
+
+/// Whether the repository has no commits yet (`HEAD` is an unborn branch).
+pub fn is_empty_repo(ctx: &GitContext, repo_path: &Path) -> Result<bool, GitError> {
+    let output = run_git(
+        ctx,
+        Some(repo_path),
+        &git_args(&["rev-parse", "--verify", "--quiet", "HEAD"]),
+    )?;
+    Ok(!output.status.success())
+}
+
+/// Asks `url` for its `HEAD` with `git ls-remote`, without a local
+/// repository. An empty repository has no `HEAD` to list (exit code 2) but
+/// still counts as reachable.
+pub fn check_remote_reachable(ctx: &GitContext, url: &str) -> Result<(), GitError> {
+    let args = git_args(&["ls-remote", "--exit-code", url, "HEAD"]);
+    let output = run_git(ctx, None, &args)?;
+    if output.status.success() || output.status.code() == Some(2) {
         Ok(())
     } else {
-        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-        let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        Err(GitError::CommandFailed {
-            project_name: project_name.to_string(),
-            command: format!("git clone {} {}", repo_url, target_path.display()),
-            stdout,
-            stderr,
-        })
+        Err(command_failed(ctx, &args, &output))
     }
 }
 
-pub fn get_current_branch(repo_path: &Path, project_name: &str) -> Result<String, GitError> {
-    let output = Command::new("git")
-        .current_dir(repo_path)
-        .arg("rev-parse")
-        .arg("--abbrev-ref")
-        .arg("HEAD")
-        .output()
-        .map_err(|e| GitError::CommandExecution {
-            project_name: project_name.to_string(),
-            command: "git rev-parse --abbrev-ref HEAD".to_string(),
-            source: e,
-        })?;
+/// Points the unborn `HEAD` of an empty repository at `refs/heads/<branch>`.
+pub fn set_initial_branch(
+    ctx: &GitContext,
+    repo_path: &Path,
+    branch: &str,
+) -> Result<(), GitError> {
+    let head_ref = format!("refs/heads/{}", branch);
+    run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["symbolic-ref", "HEAD", &head_ref]),
+    )?;
+    log_to_file(
+        LogLevel::Success,
+        &format!(
+            "Project '{}': Set initial branch of empty repository to '{}'.",
+            ctx.project_name, branch
+        ),
+    );
+    Ok(())
+}
+
+/// What `HEAD` points at: a branch, or a commit when detached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BranchState {
+    Named(String),
+    /// Detached at this full commit hash.
+    Detached(String),
+}
+
+impl fmt::Display for BranchState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BranchState::Named(name) => f.write_str(name),
+            BranchState::Detached(commit) => {
+                write!(f, "detached HEAD at {}", abbreviate_hash(commit))
+            }
+        }
+    }
+}
+
+/// Reads the checked-out branch. `rev-parse --abbrev-ref` prints the literal
+/// `HEAD` when detached, in which case the commit is recorded instead.
+pub fn get_current_branch(ctx: &GitContext, repo_path: &Path) -> Result<BranchState, GitError> {
+    let output = run_git(
+        ctx,
+        Some(repo_path),
+        &git_args(&["rev-parse", "--abbrev-ref", "HEAD"]),
+    )?;
 
     if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if name == "HEAD" {
+            return get_head_commit(ctx, repo_path).map(BranchState::Detached);
+        }
+        Ok(BranchState::Named(name))
     } else {
         Err(GitError::BranchInfoError {
-            project_name: project_name.to_string(),
+            project_name: ctx.project_name.to_string(),
             message: format!(
                 "Failed to get current branch. Git stderr: {}",
                 String::from_utf8_lossy(&output.stderr).trim()
@@ -79,121 +974,1084 @@ pub fn get_current_branch(repo_path: &Path, project_name: &str) -> Result<String
     }
 }
 
-pub fn checkout_branch(
+pub fn checkout_branch(ctx: &GitContext, repo_path: &Path, branch: &str) -> Result<(), GitError> {
+    let msg = format!(
+        "Project '{}': Attempting to checkout branch '{}'...",
+        ctx.project_name, branch
+    );
+    ctx.progress_bar.set_message(msg.clone());
+    log_to_file(LogLevel::Info, &msg);
+
+    run_git_checked(ctx, Some(repo_path), &git_args(&["checkout", branch]))?;
+
+    let success_msg = format!(
+        "Project '{}': Successfully checked out branch '{}'.",
+        ctx.project_name, branch
+    );
+    ctx.progress_bar.set_message(success_msg.clone());
+    log_to_file(LogLevel::Success, &success_msg);
+    Ok(())
+}
+
+/// Puts `HEAD` back to `state`: checks out the branch, or detaches at the
+/// recorded commit.
+pub fn restore_branch_state(
+    ctx: &GitContext,
     repo_path: &Path,
-    branch: &str,
-    project_name: &str,
-    progress_bar: &ProgressBar,
+    state: &BranchState,
 ) -> Result<(), GitError> {
+    match state {
+        BranchState::Named(branch) => checkout_branch(ctx, repo_path, branch),
+        BranchState::Detached(commit) => {
+            run_git_checked(
+                ctx,
+                Some(repo_path),
+                &git_args(&["checkout", "--detach", commit]),
+            )?;
+            log_to_file(
+                LogLevel::Success,
+                &format!("Project '{}': Restored {}.", ctx.project_name, state),
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Fetches tags from the project's remote and checks out `tag`, leaving `HEAD` detached
+/// at the tagged commit.
+pub fn checkout_tag(ctx: &GitContext, repo_path: &Path, tag: &str) -> Result<(), GitError> {
     let msg = format!(
-        "Project '{}': Attempting to checkout branch '{}'...",
-        project_name, branch
+        "Project '{}': Fetching tags to check out '{}'...",
+        ctx.project_name, tag
     );
-    progress_bar.set_message(msg.clone());
+    ctx.progress_bar.set_message(msg.clone());
     log_to_file(LogLevel::Info, &msg);
+    run_git_retrying(
+        ctx,
+        Some(repo_path),
+        &git_args(&["fetch", "--tags", ctx.remote]),
+    )?;
 
-    let output = Command::new("git")
-        .current_dir(repo_path)
-        .arg("checkout")
-        .arg(branch)
-        .output()
-        .map_err(|e| GitError::CommandExecution {
-            project_name: project_name.to_string(),
-            command: format!("git checkout {}", branch),
-            source: e,
-        })?;
+    let tag_ref = format!("refs/tags/{}", tag);
+    run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["checkout", "--detach", &tag_ref]),
+    )?;
 
-    if output.status.success() {
-        let success_msg = format!(
-            "Project '{}': Successfully checked out branch '{}'.",
-            project_name, branch
+    let success_msg = format!(
+        "Project '{}': Checked out tag '{}'; HEAD is detached at the tagged commit (expected for checkout_tag).",
+        ctx.project_name, tag
+    );
+    ctx.progress_bar
+        .set_message(format!("{} - At tag {}", ctx.project_name, tag));
+    log_to_file(LogLevel::Success, &success_msg);
+    Ok(())
+}
+
+/// Discards all local changes, including untracked and ignored files, by
+/// running `git reset --hard` followed by `git clean -fdx`.
+pub fn clean_worktree(ctx: &GitContext, repo_path: &Path) -> Result<(), GitError> {
+    let msg = format!(
+        "Project '{}': clean_worktree is set, discarding local changes and untracked files in '{}'.",
+        ctx.project_name,
+        repo_path.display()
+    );
+    ctx.progress_bar
+        .set_message(format!("{} - Cleaning working tree", ctx.project_name));
+    log_to_file(LogLevel::Warning, &msg);
+
+    run_git_checked(ctx, Some(repo_path), &git_args(&["reset", "--hard"]))?;
+    let clean_output = run_git_checked(ctx, Some(repo_path), &git_args(&["clean", "-fdx"]))?;
+    let removed = String::from_utf8_lossy(&clean_output.stdout);
+    if !removed.trim().is_empty() {
+        log_to_file(
+            LogLevel::Warning,
+            &format!(
+                "Project '{}': git clean removed:\n{}",
+                ctx.project_name,
+                truncate_output(removed.trim())
+            ),
         );
-        progress_bar.set_message(success_msg.clone());
-        log_to_file(LogLevel::Success, &success_msg);
-        Ok(())
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-        let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        Err(GitError::CommandFailed {
-            project_name: project_name.to_string(),
-            command: format!("git checkout {}", branch),
-            stdout,
-            stderr,
-        })
     }
+    Ok(())
+}
+
+/// Deletes remote-tracking refs of `ctx.remote` whose branches no longer
+/// exist upstream with `git remote prune`. Returns the pruned refs.
+pub fn prune_remote(ctx: &GitContext, repo_path: &Path) -> Result<Vec<String>, GitError> {
+    ctx.progress_bar.set_message(format!(
+        "{} - Pruning stale branches of {}",
+        ctx.project_name, ctx.remote
+    ));
+    let output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["remote", "prune", ctx.remote]),
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("* [pruned] "))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Runs `git gc --auto`, which only packs when git's own thresholds for
+/// loose objects and packs are exceeded, or a full `git gc` when `full` is
+/// set. Returns how long it took.
+pub fn run_gc(ctx: &GitContext, repo_path: &Path, full: bool) -> Result<Duration, GitError> {
+    ctx.progress_bar
+        .set_message(format!("{} - Running git gc", ctx.project_name));
+    let started = Instant::now();
+    let args = if full {
+        git_args(&["gc"])
+    } else {
+        git_args(&["gc", "--auto"])
+    };
+    run_git_checked(ctx, Some(repo_path), &args)?;
+    Ok(started.elapsed())
 }
 
-pub fn pull_branch_updates( // Renamed from pull_branch to avoid conflict with Option<&str> branch
+pub fn pull_branch_updates(
+    // Renamed from pull_branch to avoid conflict with Option<&str> branch
+    ctx: &GitContext,
     repo_path: &Path,
-    branch_to_pull: Option<&str>,
-    project_name: &str,
-    progress_bar: &ProgressBar,
+    options: &PullOptions,
 ) -> Result<(), GitError> {
-    let branch_display_name = branch_to_pull.unwrap_or("current branch");
+    if options.fetch_only {
+        return fetch_branch_updates(ctx, repo_path, options);
+    }
+    let branch_display_name = options.branch.unwrap_or("current branch");
     let pull_msg = format!(
         "Project '{}': Pulling updates for {}...",
-        project_name, branch_display_name
+        ctx.project_name, branch_display_name
     );
-    progress_bar.set_message(pull_msg.clone());
+    ctx.progress_bar.set_message(pull_msg.clone());
     log_to_file(LogLevel::Info, &pull_msg);
 
-    let mut git_pull_cmd = Command::new("git");
-    git_pull_cmd.current_dir(repo_path).arg("pull");
+    let pull_output = run_git_retrying(ctx, Some(repo_path), &options.args())?;
+    if is_dry_run() {
+        return Ok(());
+    }
 
-    let command_string = if let Some(branch) = branch_to_pull {
-        git_pull_cmd.arg("origin").arg(branch);
-        format!("git pull origin {}", branch)
+    let stdout_str = String::from_utf8_lossy(&pull_output.stdout);
+    if stdout_str.contains("Already up to date.") || stdout_str.contains("Bereits aktuell.") {
+        let msg = format!(
+            "Project '{}' ({}) is already up to date.",
+            ctx.project_name, branch_display_name
+        );
+        ctx.progress_bar.set_message(msg.clone());
+        log_to_file(LogLevel::Info, &msg);
     } else {
-        "git pull".to_string()
-    };
-
-    let pull_output = git_pull_cmd
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|e| GitError::CommandExecution {
-            project_name: project_name.to_string(),
-            command: command_string.clone(),
-            source: e,
-        })?;
-
-    if pull_output.status.success() {
-        let stdout_str = String::from_utf8_lossy(&pull_output.stdout);
-        if stdout_str.contains("Already up to date.") || stdout_str.contains("Bereits aktuell.") {
-            let msg = format!(
-                "Project '{}' ({}) is already up to date.",
-                project_name, branch_display_name
-            );
-            progress_bar.set_message(msg.clone());
-            log_to_file(LogLevel::Info, &msg);
-        } else {
-            let msg = format!(
-                "Project '{}': Successfully pulled updates for {}.",
-                project_name, branch_display_name
+        let msg = format!(
+            "Project '{}': Successfully pulled updates for {}.",
+            ctx.project_name, branch_display_name
+        );
+        ctx.progress_bar.set_message(msg.clone());
+        log_to_file(LogLevel::Success, &msg);
+        if !stdout_str.trim().is_empty() {
+            log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Git pull output for '{}' ({}):\n{}",
+                    ctx.project_name,
+                    branch_display_name,
+                    redact_text(&truncate_output(stdout_str.trim()))
+                ),
             );
-            progress_bar.set_message(msg.clone());
-            log_to_file(LogLevel::Success, &msg);
-            if !stdout_str.trim().is_empty() {
-                log_to_file(
-                    LogLevel::Info,
-                    &format!(
-                        "Git pull output for '{}' ({}):\n{}",
-                        project_name,
-                        branch_display_name,
-                        stdout_str.trim()
-                    ),
-                );
-            }
         }
+    }
+    Ok(())
+}
+
+/// Whether `error` is a pull that stopped on a merge conflict, or that git
+/// refused because an earlier merge was left unfinished.
+pub fn is_merge_conflict(error: &GitError) -> bool {
+    const MARKERS: [&str; 4] = [
+        "CONFLICT (",
+        "Automatic merge failed",
+        "unmerged files",
+        "MERGE_HEAD exists",
+    ];
+    match error {
+        GitError::CommandFailed { stdout, stderr, .. } => MARKERS
+            .iter()
+            .any(|marker| stdout.contains(marker) || stderr.contains(marker)),
+        _ => false,
+    }
+}
+
+/// Throws away the work tree and local commits of `branch` (the current
+/// branch's upstream when `None`) by resetting it hard to the remote-tracking
+/// branch, which also ends a half-finished merge. Only used with
+/// `reset_on_conflict`, and always reported as a warning.
+pub fn reset_hard_to_remote(
+    ctx: &GitContext,
+    repo_path: &Path,
+    branch: Option<&str>,
+) -> Result<(), GitError> {
+    let target = match branch {
+        Some(branch) => format!("{}/{}", ctx.remote, branch),
+        None => "@{upstream}".to_string(),
+    };
+    ctx.progress_bar
+        .set_message(format!("{} - Resetting to {}", ctx.project_name, target));
+    run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["reset", "--hard", &target]),
+    )?;
+    warn_loudly(
+        ctx,
+        &format!(
+            "Project '{}': Pull hit a merge conflict; reset hard to {}, discarding local changes and commits (reset_on_conflict).",
+            ctx.project_name, target
+        ),
+    );
+    Ok(())
+}
+
+/// Runs `git merge --abort` when a previous run left a merge unfinished, so
+/// the conflicted files do not block this one. Returns whether it did.
+pub fn abort_unfinished_merge(ctx: &GitContext, repo_path: &Path) -> Result<bool, GitError> {
+    if resolve_commit(ctx, repo_path, "MERGE_HEAD")?.is_none() {
+        return Ok(false);
+    }
+    run_git_checked(ctx, Some(repo_path), &git_args(&["merge", "--abort"]))?;
+    warn_loudly(
+        ctx,
+        &format!(
+            "Project '{}': Aborted a merge left unfinished by an earlier pull, discarding its conflict resolutions (reset_on_conflict).",
+            ctx.project_name
+        ),
+    );
+    Ok(true)
+}
+
+/// Logs a warning and prints it above the progress bars, for actions that
+/// discard the user's work.
+fn warn_loudly(ctx: &GitContext, message: &str) {
+    log_to_file(LogLevel::Warning, message);
+    ctx.progress_bar
+        .suspend(|| print_level_status(LogLevel::Warning, message));
+}
+
+/// The `fetch_only` half of [`pull_branch_updates`]. Fetch reports on
+/// stderr; any ref update other than `FETCH_HEAD` means new commits arrived.
+fn fetch_branch_updates(
+    ctx: &GitContext,
+    repo_path: &Path,
+    options: &PullOptions,
+) -> Result<(), GitError> {
+    let fetch_display_name = match options.branch {
+        Some(branch) => format!("{}/{}", options.remote, branch),
+        None => "all remotes".to_string(),
+    };
+    let fetch_msg = format!(
+        "Project '{}': Fetching {}...",
+        ctx.project_name, fetch_display_name
+    );
+    ctx.progress_bar.set_message(fetch_msg.clone());
+    log_to_file(LogLevel::Info, &fetch_msg);
+
+    let fetch_output = run_git_retrying(ctx, Some(repo_path), &options.args())?;
+    if is_dry_run() {
+        return Ok(());
+    }
+
+    let stderr_str = String::from_utf8_lossy(&fetch_output.stderr);
+    let updated_refs = stderr_str
+        .lines()
+        .any(|line| line.contains("->") && !line.trim_end().ends_with("FETCH_HEAD"));
+    if updated_refs {
+        let msg = format!(
+            "Project '{}': Fetched updates for {}.",
+            ctx.project_name, fetch_display_name
+        );
+        ctx.progress_bar.set_message(msg.clone());
+        log_to_file(LogLevel::Success, &msg);
+        log_to_file(
+            LogLevel::Info,
+            &format!(
+                "Git fetch output for '{}' ({}):\n{}",
+                ctx.project_name,
+                fetch_display_name,
+                redact_text(&truncate_output(stderr_str.trim()))
+            ),
+        );
+    } else {
+        let msg = format!(
+            "Project '{}' ({}) is already up to date.",
+            ctx.project_name, fetch_display_name
+        );
+        ctx.progress_bar.set_message(msg.clone());
+        log_to_file(LogLevel::Info, &msg);
+    }
+    Ok(())
+}
+
+pub fn get_remote_url(
+    ctx: &GitContext,
+    repo_path: &Path,
+    remote: &str,
+) -> Result<String, GitError> {
+    let output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["remote", "get-url", remote]),
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Points the project's remote at `url` (`git remote set-url`).
+pub fn set_remote_url(ctx: &GitContext, repo_path: &Path, url: &str) -> Result<(), GitError> {
+    run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["remote", "set-url", ctx.remote, url]),
+    )?;
+    Ok(())
+}
+
+/// Returns whether the repository behind `repo_url` shares any history with
+/// the clone at `repo_path`. The remote HEAD is looked up locally first; only
+/// if it is unknown is it fetched (into FETCH_HEAD) and checked for a merge base.
+pub fn remote_shares_history(
+    ctx: &GitContext,
+    repo_path: &Path,
+    repo_url: &str,
+) -> Result<bool, GitError> {
+    let ls_remote_output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["ls-remote", repo_url, "HEAD"]),
+    )?;
+    let remote_head = String::from_utf8_lossy(&ls_remote_output.stdout)
+        .split_whitespace()
+        .next()
+        .map(str::to_string);
+    let Some(remote_head) = remote_head else {
+        // An empty remote has no history to compare against.
+        return Ok(false);
+    };
+
+    let commit_object = format!("{}^{{commit}}", remote_head);
+    let known_locally = run_git(
+        ctx,
+        Some(repo_path),
+        &git_args(&["cat-file", "-e", &commit_object]),
+    )?
+    .status
+    .success();
+    if known_locally {
+        return Ok(true);
+    }
+
+    run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["fetch", "--quiet", repo_url, "HEAD"]),
+    )?;
+
+    let merge_base_args = git_args(&["merge-base", "HEAD", "FETCH_HEAD"]);
+    let merge_base_output = run_git(ctx, Some(repo_path), &merge_base_args)?;
+    match merge_base_output.status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        _ => Err(command_failed(ctx, &merge_base_args, &merge_base_output)),
+    }
+}
+
+/// Reads `file_path` as it was at `git_ref` in the repository containing it.
+pub fn read_file_at_revision(
+    ctx: &GitContext,
+    file_path: &Path,
+    git_ref: &str,
+) -> Result<String, GitError> {
+    let repo_dir = file_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let object = format!("{}:./{}", git_ref, file_name);
+    let output = run_git_checked(ctx, Some(repo_dir), &git_args(&["show", &object]))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Checks the signature on the commit `HEAD` points at with
+/// `git verify-commit`, using whatever keys GPG (or SSH signing) trusts.
+pub fn verify_head_signature(ctx: &GitContext, repo_path: &Path) -> Result<(), GitError> {
+    ctx.progress_bar.set_message(format!(
+        "Verifying commit signature for '{}'...",
+        ctx.project_name
+    ));
+    let output = run_git(ctx, Some(repo_path), &git_args(&["verify-commit", "HEAD"]))?;
+    if output.status.success() {
+        log_to_file(
+            LogLevel::Success,
+            &format!(
+                "Project '{}': HEAD has a valid signature.",
+                ctx.project_name
+            ),
+        );
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(GitError::SignatureVerificationFailed {
+            project_name: ctx.project_name.to_string(),
+            message: if stderr.trim().is_empty() {
+                "HEAD is not signed".to_string()
+            } else {
+                redact_text(&truncate_output(stderr.trim()))
+            },
+        })
+    }
+}
+
+/// A commit as listed in changelogs: hash abbreviated to `hash_length` and
+/// subject line.
+#[derive(Serialize, Debug, Clone)]
+pub struct CommitSummary {
+    pub hash: String,
+    pub subject: String,
+}
+
+pub fn get_head_commit(ctx: &GitContext, repo_path: &Path) -> Result<String, GitError> {
+    let output = run_git_checked(ctx, Some(repo_path), &git_args(&["rev-parse", "HEAD"]))?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Commit the local tag `tag` points at, or `None` when there is no such tag.
+pub fn get_tag_commit(
+    ctx: &GitContext,
+    repo_path: &Path,
+    tag: &str,
+) -> Result<Option<String>, GitError> {
+    resolve_commit(ctx, repo_path, &format!("refs/tags/{}", tag))
+}
+
+/// Commit `reference` points at, or `None` when it does not exist.
+pub fn resolve_commit(
+    ctx: &GitContext,
+    repo_path: &Path,
+    reference: &str,
+) -> Result<Option<String>, GitError> {
+    let commit_object = format!("{}^{{commit}}", reference);
+    let output = run_git(
+        ctx,
+        Some(repo_path),
+        &git_args(&["rev-parse", "--verify", "--quiet", &commit_object]),
+    )?;
+    Ok(output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+}
+
+/// Lists the commits reachable from `new_commit` but not `old_commit`, newest first.
+pub fn list_commits_between(
+    ctx: &GitContext,
+    repo_path: &Path,
+    old_commit: &str,
+    new_commit: &str,
+) -> Result<Vec<CommitSummary>, GitError> {
+    let range = format!("{}..{}", old_commit, new_commit);
+    let output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["log", "--format=%H%x09%s", &range]),
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(hash, subject)| CommitSummary {
+            hash: abbreviate_hash(hash),
+            subject: subject.to_string(),
+        })
+        .collect())
+}
+
+/// `git diff --shortstat` between two commits, e.g. `12 files changed, 40
+/// insertions(+), 3 deletions(-)`; empty when no file changed.
+pub fn diff_shortstat(
+    ctx: &GitContext,
+    repo_path: &Path,
+    old_commit: &str,
+    new_commit: &str,
+) -> Result<String, GitError> {
+    let output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["diff", "--shortstat", old_commit, new_commit]),
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Reads a repository-local git config value, `None` when it is unset.
+pub fn get_config_value(
+    ctx: &GitContext,
+    repo_path: &Path,
+    key: &str,
+) -> Result<Option<String>, GitError> {
+    let args = git_args(&["config", "--local", "--get", key]);
+    let output = run_git(ctx, Some(repo_path), &args)?;
+    match output.status.code() {
+        Some(0) => Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        )),
+        Some(1) => Ok(None),
+        _ => Err(command_failed(ctx, &args, &output)),
+    }
+}
+
+/// Lists branch names on `remote` via `git ls-remote --heads`.
+pub fn list_remote_branches(
+    ctx: &GitContext,
+    repo_path: &Path,
+    remote: &str,
+) -> Result<Vec<String>, GitError> {
+    let output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["ls-remote", "--heads", remote]),
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .filter_map(|reference| reference.strip_prefix("refs/heads/"))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Brings a bare clone up to date. Mirrors run `git remote update --prune`,
+/// which follows their `+refs/*:refs/*` refspec; plain bare clones have no
+/// fetch refspec, so their branches are fetched onto `refs/heads` directly.
+pub fn sync_bare_clone(ctx: &GitContext, repo_path: &Path, mirror: bool) -> Result<(), GitError> {
+    let msg = format!(
+        "Project '{}': Updating bare clone from '{}'...",
+        ctx.project_name, ctx.remote
+    );
+    ctx.progress_bar.set_message(msg.clone());
+    log_to_file(LogLevel::Info, &msg);
+
+    let args = if mirror {
+        git_args(&["remote", "update", "--prune"])
+    } else {
+        git_args(&["fetch", "--prune", ctx.remote, "+refs/heads/*:refs/heads/*"])
+    };
+    run_git_retrying(ctx, Some(repo_path), &args)?;
+    Ok(())
+}
+
+/// Every local branch with the commit it points at.
+pub fn list_branch_heads(
+    ctx: &GitContext,
+    repo_path: &Path,
+) -> Result<Vec<(String, String)>, GitError> {
+    let output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&[
+            "for-each-ref",
+            "--format=%(refname:short) %(objectname)",
+            "refs/heads",
+        ]),
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(branch, commit)| (branch.to_string(), commit.to_string()))
+        .collect())
+}
+
+/// Fails with [`GitError::MissingRemote`] when the project's remote is not
+/// configured in the clone at `repo_path`.
+pub fn ensure_remote_exists(ctx: &GitContext, repo_path: &Path) -> Result<(), GitError> {
+    let output = run_git(
+        ctx,
+        Some(repo_path),
+        &git_args(&["remote", "get-url", ctx.remote]),
+    )?;
+    if output.status.success() {
         Ok(())
     } else {
-        let stderr_str = String::from_utf8_lossy(&pull_output.stderr).trim().to_string();
-        let stdout_str = String::from_utf8_lossy(&pull_output.stdout).trim().to_string();
-        Err(GitError::CommandFailed {
-            project_name: project_name.to_string(),
-            command: command_string,
-            stdout: stdout_str,
-            stderr: stderr_str,
+        Err(GitError::MissingRemote {
+            project_name: ctx.project_name.to_string(),
+            remote: ctx.remote.to_string(),
+            path: repo_path.to_path_buf(),
+        })
+    }
+}
+
+/// Name of `remote`'s default branch: read from the local `<remote>/HEAD`
+/// when the clone recorded it, otherwise asked from the remote.
+pub fn get_remote_default_branch(
+    ctx: &GitContext,
+    repo_path: &Path,
+    remote: &str,
+) -> Result<String, GitError> {
+    let head_ref = format!("{}/HEAD", remote);
+    let output = run_git(
+        ctx,
+        Some(repo_path),
+        &git_args(&["rev-parse", "--abbrev-ref", &head_ref]),
+    )?;
+    let local_prefix = format!("{}/", remote);
+    if output.status.success() {
+        if let Some(branch) = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .strip_prefix(&local_prefix)
+        {
+            return Ok(branch.to_string());
+        }
+    }
+
+    let output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["ls-remote", "--symref", remote, "HEAD"]),
+    )?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("ref: refs/heads/"))
+        .and_then(|rest| rest.split_whitespace().next())
+        .map(str::to_string)
+        .ok_or_else(|| GitError::BranchInfoError {
+            project_name: ctx.project_name.to_string(),
+            message: format!("'{}' did not report a default branch", remote),
         })
+}
+
+/// Updates remote-tracking refs for `remote` without touching the work tree.
+pub fn fetch_remote(ctx: &GitContext, repo_path: &Path, remote: &str) -> Result<(), GitError> {
+    log_to_file(
+        LogLevel::Info,
+        &format!("Project '{}': Fetching '{}'...", ctx.project_name, remote),
+    );
+    run_git_checked(ctx, Some(repo_path), &git_args(&["fetch", remote]))?;
+    Ok(())
+}
+
+/// Makes `ctx.remote` fetch only `branch`, as a `--single-branch` clone of
+/// it would, and fetches it if the refspec had to change. Returns whether it
+/// did.
+pub fn ensure_single_branch_tracked(
+    ctx: &GitContext,
+    repo_path: &Path,
+    branch: &str,
+) -> Result<bool, GitError> {
+    let key = format!("remote.{}.fetch", ctx.remote);
+    let args = git_args(&["config", "--local", "--get-all", &key]);
+    let output = run_git(ctx, Some(repo_path), &args)?;
+    if !matches!(output.status.code(), Some(0 | 1)) {
+        return Err(command_failed(ctx, &args, &output));
     }
-}
\ No newline at end of file
+    let expected = format!(
+        "+refs/heads/{}:refs/remotes/{}/{}",
+        branch, ctx.remote, branch
+    );
+    let refspecs = String::from_utf8_lossy(&output.stdout);
+    if refspecs.lines().eq([expected.as_str()]) {
+        return Ok(false);
+    }
+
+    log_to_file(
+        LogLevel::Info,
+        &format!(
+            "Project '{}': Tracking only branch '{}' of '{}' (single_branch).",
+            ctx.project_name, branch, ctx.remote
+        ),
+    );
+    run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["remote", "set-branches", ctx.remote, branch]),
+    )?;
+    fetch_remote(ctx, repo_path, ctx.remote)?;
+    Ok(true)
+}
+
+/// Whether the work tree or index has modifications or untracked files.
+pub fn has_uncommitted_changes(ctx: &GitContext, repo_path: &Path) -> Result<bool, GitError> {
+    let output = run_git_checked(ctx, Some(repo_path), &git_args(&["status", "--porcelain"]))?;
+    Ok(!output.stdout.iter().all(u8::is_ascii_whitespace))
+}
+
+/// Runs `git fsck` to check the object database for corruption or missing
+/// objects. Read-only, but slow on large repositories.
+pub fn check_integrity(ctx: &GitContext, repo_path: &Path) -> Result<(), GitError> {
+    run_git_checked(ctx, Some(repo_path), &git_args(&["fsck", "--no-progress"]))?;
+    Ok(())
+}
+
+/// Whether tracked files match `HEAD`, i.e. a pull cannot trip over local
+/// edits. Untracked files are ignored.
+pub fn is_working_tree_clean(ctx: &GitContext, repo_path: &Path) -> Result<bool, GitError> {
+    let output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["status", "--porcelain", "--untracked-files=no"]),
+    )?;
+    Ok(output.stdout.iter().all(u8::is_ascii_whitespace))
+}
+
+/// Stashes local changes to tracked files so a pull can proceed. They stay
+/// in the stash for the user to restore with `git stash pop`.
+pub fn stash_changes(ctx: &GitContext, repo_path: &Path) -> Result<(), GitError> {
+    ctx.progress_bar
+        .set_message(format!("{} - Stashing local changes", ctx.project_name));
+    run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&[
+            "stash",
+            "push",
+            "-m",
+            "git-project-updater: local changes before update",
+        ]),
+    )?;
+    log_to_file(
+        LogLevel::Warning,
+        &format!(
+            "Project '{}': Stashed local changes in '{}' (--force); restore them with 'git stash pop'.",
+            ctx.project_name,
+            repo_path.display()
+        ),
+    );
+    Ok(())
+}
+
+/// Commits `HEAD` is ahead of and behind its upstream, or `None` when the
+/// current branch has no upstream (or `HEAD` is detached).
+pub fn get_ahead_behind(
+    ctx: &GitContext,
+    repo_path: &Path,
+) -> Result<Option<(usize, usize)>, GitError> {
+    let output = run_git(
+        ctx,
+        Some(repo_path),
+        &git_args(&["rev-list", "--left-right", "--count", "HEAD...@{upstream}"]),
+    )?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let counts = String::from_utf8_lossy(&output.stdout);
+    let mut counts = counts.split_whitespace().map(str::parse::<usize>);
+    match (counts.next(), counts.next()) {
+        (Some(Ok(ahead)), Some(Ok(behind))) => Ok(Some((ahead, behind))),
+        _ => Ok(None),
+    }
+}
+
+/// Initializes and updates submodules recursively; only those under `paths`
+/// when it is non-empty.
+pub fn update_submodules(
+    ctx: &GitContext,
+    repo_path: &Path,
+    paths: &[String],
+) -> Result<(), GitError> {
+    let msg = format!("Project '{}': Updating submodules...", ctx.project_name);
+    ctx.progress_bar.set_message(msg.clone());
+    log_to_file(LogLevel::Info, &msg);
+    let mut args = git_args(&["submodule", "update", "--init", "--recursive"]);
+    if !paths.is_empty() {
+        args.push("--".to_string());
+        args.extend(paths.iter().cloned());
+    }
+    run_git_checked(ctx, Some(repo_path), &args)?;
+    Ok(())
+}
+
+/// Submodule path to pinned commit, as listed by `git submodule status`.
+pub fn get_submodule_pins(
+    ctx: &GitContext,
+    repo_path: &Path,
+) -> Result<Vec<(String, String)>, GitError> {
+    let output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["submodule", "status", "--recursive"]),
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            // Lines look like "[ -+U]<sha> <path> (<describe>)".
+            let mut fields = line.get(1..)?.split_whitespace();
+            let sha = fields.next()?;
+            let path = fields.next()?;
+            Some((path.to_string(), sha.to_string()))
+        })
+        .collect())
+}
+
+pub fn is_shallow_clone(ctx: &GitContext, repo_path: &Path) -> Result<bool, GitError> {
+    let output = run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["rev-parse", "--is-shallow-repository"]),
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim() == "true")
+}
+
+/// Fetches the complete history of a shallow clone.
+pub fn unshallow_history(ctx: &GitContext, repo_path: &Path) -> Result<(), GitError> {
+    let msg = format!(
+        "Project '{}': Fetching full history (depth 0)...",
+        ctx.project_name
+    );
+    ctx.progress_bar.set_message(msg.clone());
+    log_to_file(LogLevel::Info, &msg);
+    run_git_checked(ctx, Some(repo_path), &git_args(&["fetch", "--unshallow"]))?;
+    Ok(())
+}
+
+/// Extends a shallow clone's history by `commits` commits.
+pub fn deepen_history(ctx: &GitContext, repo_path: &Path, commits: u32) -> Result<(), GitError> {
+    let msg = format!(
+        "Project '{}': Deepening shallow history by {} commit(s)...",
+        ctx.project_name, commits
+    );
+    ctx.progress_bar.set_message(msg.clone());
+    log_to_file(LogLevel::Info, &msg);
+    run_git_checked(
+        ctx,
+        Some(repo_path),
+        &git_args(&["fetch", &format!("--deepen={}", commits)]),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clone_options() -> CloneOptions<'static> {
+        CloneOptions {
+            depth: None,
+            reference: None,
+            dissociate: true,
+            submodule_paths: &[],
+            remote: DEFAULT_REMOTE,
+            bare: false,
+            mirror: false,
+            single_branch: None,
+        }
+    }
+
+    fn clone_args(options: &CloneOptions) -> Vec<String> {
+        options.args("https://example.com/repo.git", Path::new("/work/repo"))
+    }
+
+    fn delays(jitter: RetryJitter, seed: u64) -> Vec<u128> {
+        let mut rng = JitterRng::new(seed);
+        (1..=5)
+            .map(|attempt| {
+                retry_delay(Duration::from_millis(1000), attempt, jitter, &mut rng).as_millis()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn retry_delay_without_jitter_doubles() {
+        assert_eq!(
+            delays(RetryJitter::None, 7),
+            [1000, 2000, 4000, 8000, 16000]
+        );
+    }
+
+    #[test]
+    fn retry_delay_full_jitter_stays_below_the_doubling_delay() {
+        let schedule = delays(RetryJitter::Full, 7);
+        for (delay, cap) in schedule.iter().zip([1000, 2000, 4000, 8000, 16000]) {
+            assert!(*delay <= cap, "{} > {}", delay, cap);
+        }
+        assert_eq!(schedule, delays(RetryJitter::Full, 7));
+        assert_ne!(schedule, delays(RetryJitter::Full, 8));
+    }
+
+    #[test]
+    fn retry_delay_equal_jitter_keeps_half_the_doubling_delay() {
+        let schedule = delays(RetryJitter::Equal, 7);
+        for (delay, cap) in schedule.iter().zip([1000, 2000, 4000, 8000, 16000]) {
+            assert!(
+                cap / 2 <= *delay && *delay <= cap,
+                "{} outside {}..={}",
+                delay,
+                cap / 2,
+                cap
+            );
+        }
+        assert_eq!(schedule, delays(RetryJitter::Equal, 7));
+    }
+
+    #[test]
+    fn retry_delay_with_zero_base_stays_zero() {
+        let mut rng = JitterRng::new(1);
+        for jitter in [RetryJitter::None, RetryJitter::Full, RetryJitter::Equal] {
+            assert_eq!(
+                retry_delay(Duration::ZERO, 3, jitter, &mut rng),
+                Duration::ZERO
+            );
+        }
+    }
+
+    #[test]
+    fn plain_clone_passes_only_url_and_path() {
+        assert_eq!(
+            clone_args(&clone_options()),
+            ["clone", "https://example.com/repo.git", "/work/repo"]
+        );
+    }
+
+    #[test]
+    fn clone_depth_zero_means_full_history() {
+        let options = CloneOptions {
+            depth: Some(0),
+            ..clone_options()
+        };
+        assert_eq!(
+            clone_args(&options),
+            ["clone", "https://example.com/repo.git", "/work/repo"]
+        );
+    }
+
+    #[test]
+    fn shallow_single_branch_clone() {
+        let options = CloneOptions {
+            depth: Some(1),
+            single_branch: Some("main"),
+            ..clone_options()
+        };
+        assert_eq!(
+            clone_args(&options),
+            [
+                "clone",
+                "--depth=1",
+                "--branch=main",
+                "--single-branch",
+                "https://example.com/repo.git",
+                "/work/repo",
+            ]
+        );
+    }
+
+    #[test]
+    fn clone_with_submodules_remote_and_reference() {
+        let submodule_paths = ["libs/core".to_string(), "libs/ui".to_string()];
+        let options = CloneOptions {
+            depth: Some(5),
+            reference: Some(PathBuf::from("/cache/repo.git")),
+            submodule_paths: &submodule_paths,
+            remote: "upstream",
+            ..clone_options()
+        };
+        assert_eq!(
+            clone_args(&options),
+            [
+                "clone",
+                "--depth=5",
+                "--recurse-submodules=libs/core",
+                "--recurse-submodules=libs/ui",
+                "--origin=upstream",
+                "--reference",
+                "/cache/repo.git",
+                "--dissociate",
+                "https://example.com/repo.git",
+                "/work/repo",
+            ]
+        );
+    }
+
+    #[test]
+    fn reference_without_dissociate() {
+        let options = CloneOptions {
+            reference: Some(PathBuf::from("/cache/repo.git")),
+            dissociate: false,
+            ..clone_options()
+        };
+        assert_eq!(
+            clone_args(&options),
+            [
+                "clone",
+                "--reference",
+                "/cache/repo.git",
+                "https://example.com/repo.git",
+                "/work/repo",
+            ]
+        );
+    }
+
+    #[test]
+    fn mirror_wins_over_bare() {
+        let options = CloneOptions {
+            bare: true,
+            mirror: true,
+            ..clone_options()
+        };
+        assert_eq!(
+            clone_args(&options),
+            [
+                "clone",
+                "--mirror",
+                "https://example.com/repo.git",
+                "/work/repo"
+            ]
+        );
+        let options = CloneOptions {
+            bare: true,
+            ..clone_options()
+        };
+        assert_eq!(
+            clone_args(&options),
+            [
+                "clone",
+                "--bare",
+                "https://example.com/repo.git",
+                "/work/repo"
+            ]
+        );
+    }
+
+    #[test]
+    fn pull_current_branch_from_upstream() {
+        let options = PullOptions {
+            remote: DEFAULT_REMOTE,
+            branch: None,
+            fetch_only: false,
+        };
+        assert_eq!(options.args(), ["pull"]);
+    }
+
+    #[test]
+    fn pull_named_branch_from_remote() {
+        let options = PullOptions {
+            remote: "upstream",
+            branch: Some("main"),
+            fetch_only: false,
+        };
+        assert_eq!(options.args(), ["pull", "upstream", "main"]);
+    }
+
+    #[test]
+    fn fetch_only_named_branch() {
+        let options = PullOptions {
+            remote: "upstream",
+            branch: Some("main"),
+            fetch_only: true,
+        };
+        assert_eq!(options.args(), ["fetch", "upstream", "main"]);
+    }
+
+    #[test]
+    fn fetch_only_without_branch_fetches_every_remote() {
+        let options = PullOptions {
+            remote: DEFAULT_REMOTE,
+            branch: None,
+            fetch_only: true,
+        };
+        assert_eq!(options.args(), ["fetch", "--all"]);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::error::ProjectError;
+use crate::logger::{log_to_file, log_trace, truncate_output, LogLevel};
+use crate::redact::redact_text;
+use indicatif::ProgressBar;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Project hooks allowed to run at once (`--hook-jobs`); 0 means no limit
+/// beyond `--jobs`.
+static HOOK_JOBS: AtomicUsize = AtomicUsize::new(0);
+static RUNNING_HOOKS: Mutex<usize> = Mutex::new(0);
+static HOOK_FINISHED: Condvar = Condvar::new();
+
+pub fn set_hook_jobs(jobs: usize) {
+    HOOK_JOBS.store(jobs, Ordering::Relaxed);
+}
+
+fn running_hooks() -> MutexGuard<'static, usize> {
+    RUNNING_HOOKS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// One of the `--hook-jobs` slots, released when dropped.
+struct HookSlot;
+
+impl HookSlot {
+    /// Blocks until fewer than `--hook-jobs` hooks are running.
+    fn acquire(project_name: &str, progress_bar: &ProgressBar) -> HookSlot {
+        let limit = HOOK_JOBS.load(Ordering::Relaxed);
+        let mut running = running_hooks();
+        if limit > 0 && *running >= limit {
+            progress_bar.set_message(format!("{} - Waiting for a hook slot", project_name));
+            while *running >= limit {
+                running = HOOK_FINISHED
+                    .wait(running)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+        }
+        *running += 1;
+        HookSlot
+    }
+}
+
+impl Drop for HookSlot {
+    fn drop(&mut self) {
+        *running_hooks() -= 1;
+        HOOK_FINISHED.notify_one();
+    }
+}
+
+/// Runs a user-supplied shell command in `repo_path`, logging its output,
+/// once a `--hook-jobs` slot is free. A non-zero exit or a failure to start
+/// the shell is a `HookFailed` error.
+pub fn run_hook(
+    repo_path: &Path,
+    command: &str,
+    project_name: &str,
+    progress_bar: &ProgressBar,
+) -> Result<(), ProjectError> {
+    let _slot = HookSlot::acquire(project_name, progress_bar);
+    let msg = format!("Project '{}': Running hook '{}'...", project_name, command);
+    progress_bar.set_message(msg.clone());
+    log_to_file(LogLevel::Info, &msg);
+
+    let started = Instant::now();
+    let output = shell_command(command).current_dir(repo_path).output();
+    log_trace(
+        &redact_text(command),
+        Some(repo_path),
+        output.as_ref().ok().and_then(|output| output.status.code()),
+        started.elapsed(),
+    );
+    let output = output.map_err(|e| ProjectError::HookFailed {
+        project_name: project_name.to_string(),
+        command: command.to_string(),
+        message: format!("failed to start shell: {}", e),
+    })?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stdout.trim().is_empty() {
+        log_to_file(
+            LogLevel::Info,
+            &format!(
+                "Hook output for '{}':\n{}",
+                project_name,
+                truncate_output(stdout.trim())
+            ),
+        );
+    }
+    if !stderr.trim().is_empty() {
+        log_to_file(
+            LogLevel::Info,
+            &format!(
+                "Hook stderr for '{}':\n{}",
+                project_name,
+                truncate_output(stderr.trim())
+            ),
+        );
+    }
+
+    if output.status.success() {
+        log_to_file(
+            LogLevel::Success,
+            &format!("Project '{}': Hook '{}' completed.", project_name, command),
+        );
+        Ok(())
+    } else {
+        Err(ProjectError::HookFailed {
+            project_name: project_name.to_string(),
+            command: command.to_string(),
+            message: format!("exited with {}", output.status),
+        })
+    }
+}
+
+/// Runs the `--on-complete` command with `input` on its stdin, logging its
+/// output. Failures are logged and never affect the run's result.
+pub fn run_on_complete(command: &str, input: &str) {
+    log_to_file(
+        LogLevel::Info,
+        &format!("Running on-complete command '{}'...", command),
+    );
+    let started = Instant::now();
+    let result = pipe_to_shell(command, input);
+    log_trace(
+        &redact_text(command),
+        None,
+        result.as_ref().ok().and_then(|output| output.status.code()),
+        started.elapsed(),
+    );
+    match result {
+        Ok(output) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !stdout.trim().is_empty() {
+                log_to_file(
+                    LogLevel::Info,
+                    &format!("On-complete output:\n{}", truncate_output(stdout.trim())),
+                );
+            }
+            if !stderr.trim().is_empty() {
+                log_to_file(
+                    LogLevel::Info,
+                    &format!("On-complete stderr:\n{}", truncate_output(stderr.trim())),
+                );
+            }
+            if output.status.success() {
+                log_to_file(
+                    LogLevel::Success,
+                    &format!("On-complete command '{}' completed.", command),
+                );
+            } else {
+                log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "On-complete command '{}' exited with {}.",
+                        command, output.status
+                    ),
+                );
+            }
+        }
+        Err(e) => log_to_file(
+            LogLevel::Warning,
+            &format!("Failed to run on-complete command '{}': {}", command, e),
+        ),
+    }
+}
+
+fn pipe_to_shell(command: &str, input: &str) -> io::Result<std::process::Output> {
+    let mut child = shell_command(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that ignores its input may close stdin early; still
+        // collect its output and exit status.
+        if let Err(e) = stdin.write_all(input.as_bytes()) {
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                return Err(e);
+            }
+        }
+    }
+    child.wait_with_output()
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
@@ -0,0 +1,126 @@
+use crate::error::AppError;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Where `--init` writes when no path is given.
+pub const DEFAULT_INIT_PATH: &str = "git-fetcher.json";
+
+/// The example shown by `--help` and written by `--init` for `.json` paths.
+pub const SAMPLE_CONFIG_JSON: &str = r#"{
+  "global_config": {
+    "default_clone_parent_directory": "~/projects/work"
+  },
+  "projects": [
+    {
+      "project": "MyCoolApp",
+      "url": "https://github.com/user/mycoolapp.git",
+      "path": "mycoolapp",
+      "pull_branches": ["main", "develop"]
+    },
+    {
+      "project": "AnotherProject",
+      "url": "https://github.com/user/anotherproject.git",
+      "path": "/absolute/path/to/anotherproject"
+    },
+    {
+      "project": "LegacySystem",
+      "url": "https://github.com/user/legacysystem.git",
+      "path": "old_stuff/legacy",
+      "pull_branches": []
+    }
+  ]
+}
+"#;
+
+/// The same example for `.yaml` and `.yml` paths, with comments.
+const SAMPLE_CONFIG_YAML: &str = r#"# Starter config for git_project_updater. See the README for every field.
+global_config:
+  # Relative project paths are cloned under this directory. A relative value
+  # is taken from the directory containing this file.
+  default_clone_parent_directory: ~/projects/work
+
+projects:
+  # Pulls main and develop, then returns to the branch that was checked out.
+  - project: MyCoolApp
+    url: https://github.com/user/mycoolapp.git
+    path: mycoolapp
+    pull_branches: [main, develop]
+
+  # Absolute paths ignore default_clone_parent_directory. Without
+  # pull_branches the checked-out branch is pulled.
+  - project: AnotherProject
+    url: https://github.com/user/anotherproject.git
+    path: /absolute/path/to/anotherproject
+
+  # An empty list pulls what global_config.empty_pull_branches_means selects,
+  # the checked-out branch by default.
+  - project: LegacySystem
+    url: https://github.com/user/legacysystem.git
+    path: old_stuff/legacy
+    pull_branches: []
+"#;
+
+/// The same example for `.toml` paths, with comments.
+const SAMPLE_CONFIG_TOML: &str = r#"# Starter config for git_project_updater. See the README for every field.
+[global_config]
+# Relative project paths are cloned under this directory. A relative value
+# is taken from the directory containing this file.
+default_clone_parent_directory = "~/projects/work"
+
+# Pulls main and develop, then returns to the branch that was checked out.
+[[projects]]
+project = "MyCoolApp"
+url = "https://github.com/user/mycoolapp.git"
+path = "mycoolapp"
+pull_branches = ["main", "develop"]
+
+# Absolute paths ignore default_clone_parent_directory. Without
+# pull_branches the checked-out branch is pulled.
+[[projects]]
+project = "AnotherProject"
+url = "https://github.com/user/anotherproject.git"
+path = "/absolute/path/to/anotherproject"
+
+# An empty list pulls what global_config.empty_pull_branches_means selects,
+# the checked-out branch by default.
+[[projects]]
+project = "LegacySystem"
+url = "https://github.com/user/legacysystem.git"
+path = "old_stuff/legacy"
+pull_branches = []
+"#;
+
+/// Writes the example config to `path` for `--init`, in the format its
+/// extension names (JSON otherwise, which cannot hold comments). An existing
+/// file is only replaced with `force`.
+pub fn write_starter_config(path: &Path, force: bool) -> Result<(), AppError> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    let content = match extension.as_deref() {
+        Some("yaml" | "yml") => SAMPLE_CONFIG_YAML,
+        Some("toml") => SAMPLE_CONFIG_TOML,
+        _ => SAMPLE_CONFIG_JSON,
+    };
+
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut file = options.open(path).map_err(|e| {
+        if e.kind() == io::ErrorKind::AlreadyExists {
+            AppError::Usage(format!(
+                "'{}' already exists; pass --force to overwrite it",
+                path.display()
+            ))
+        } else {
+            AppError::InitFile(path.to_path_buf(), e)
+        }
+    })?;
+    file.write_all(content.as_bytes())
+        .map_err(|e| AppError::InitFile(path.to_path_buf(), e))
+}
@@ -0,0 +1,72 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Advisory lock on one project, held for as long as the value lives, so two
+/// runs with overlapping project sets never work on the same clone at once.
+/// The lock file sits next to the project directory, which may not exist yet.
+/// The operating system releases the lock if the process dies, so a file left
+/// behind by a killed run does not block the next one.
+pub struct ProjectLock {
+    path: PathBuf,
+    // Dropped after `drop` has removed `path`, which releases the lock.
+    _file: File,
+}
+
+impl ProjectLock {
+    /// Takes the lock for the project at `project_path`, or returns `Ok(None)`
+    /// when another process holds it.
+    pub fn try_acquire(project_path: &Path) -> io::Result<Option<ProjectLock>> {
+        let path = lock_path(project_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        loop {
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(false)
+                .open(&path)?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => return Ok(None),
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+            // The previous holder removes the file when it finishes; if that
+            // happened after we opened it, our lock is on an orphaned file.
+            if is_same_file(&file, &path) {
+                return Ok(Some(ProjectLock { path, _file: file }));
+            }
+        }
+    }
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// `.<dir>.git-project-updater.lock` next to the project directory.
+fn lock_path(project_path: &Path) -> PathBuf {
+    let dir_name = project_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    project_path.with_file_name(format!(".{}.git-project-updater.lock", dir_name))
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(opened), Ok(current)) => opened.dev() == current.dev() && opened.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, path: &Path) -> bool {
+    // Files that are open cannot be removed here, so the path is still ours.
+    path.exists()
+}
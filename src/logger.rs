@@ -1,48 +1,433 @@
-use chrono;
-use std::fs::OpenOptions;
-use std::io::Write;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 
 const LOG_FILE_NAME: &str = "project_fetcher.log";
 
+/// Log file set by `--log-file` or `log_file`; `None` means
+/// `project_fetcher.log` in the working directory.
+static LOG_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Writes the log file at `path` (`~` expanded) from now on, creating its
+/// directory if needed.
+pub fn set_log_file(path: &str) -> io::Result<()> {
+    let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    *LOG_FILE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(path);
+    Ok(())
+}
+
+fn log_file_path() -> PathBuf {
+    LOG_FILE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(LOG_FILE_NAME))
+}
+
+/// Directory holding one log per project (`project_log_dir`); `None`
+/// disables them.
+static PROJECT_LOG_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+/// Whether lines written for a project skip the shared log.
+static PROJECT_LOG_ONLY: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The project this worker thread is processing, if any.
+    static CURRENT_PROJECT: RefCell<Option<ProjectContext>> = const { RefCell::new(None) };
+}
+
+/// What the log knows about the project a thread is working on.
+struct ProjectContext {
+    name: String,
+    /// Its own log under `project_log_dir`, when that is set.
+    log_path: Option<PathBuf>,
+}
+
+/// Also writes each project's lines to `<dir>/<project>.log` (`~` expanded),
+/// creating the directory. With `only`, those lines leave the shared log.
+pub fn set_project_log_dir(dir: &str, only: bool) -> io::Result<()> {
+    let dir = PathBuf::from(shellexpand::tilde(dir).as_ref());
+    fs::create_dir_all(&dir)?;
+    *PROJECT_LOG_DIR
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(dir);
+    PROJECT_LOG_ONLY.store(only, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Attributes the calling thread's log lines to `project` until the guard is
+/// dropped: JSON lines carry its name, and with `project_log_dir` the lines
+/// also go to its own log. Each project runs on its own worker thread, so
+/// parallel projects never share a context.
+pub fn enter_project_log(project: &str) -> ProjectLogGuard {
+    let dir = PROJECT_LOG_DIR
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    let context = ProjectContext {
+        name: project.to_string(),
+        log_path: dir.map(|dir| dir.join(format!("{}.log", project_log_stem(project)))),
+    };
+    CURRENT_PROJECT.with(|current| *current.borrow_mut() = Some(context));
+    ProjectLogGuard
+}
+
+pub struct ProjectLogGuard;
+
+impl Drop for ProjectLogGuard {
+    fn drop(&mut self) {
+        CURRENT_PROJECT.with(|current| *current.borrow_mut() = None);
+    }
+}
+
+/// `project` with anything but letters, digits, `-`, `_` and `.` replaced by
+/// `_`, so names like `team/api` stay inside the log directory.
+fn project_log_stem(project: &str) -> String {
+    project
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+pub const DEFAULT_LOG_ROTATE_SIZE_MB: u64 = 10;
+pub const DEFAULT_LOG_ROTATE_KEEP: usize = 3;
+
+/// Size at which the log file is rotated before the next line is appended.
+static LOG_ROTATE_BYTES: AtomicU64 = AtomicU64::new(DEFAULT_LOG_ROTATE_SIZE_MB * 1024 * 1024);
+/// Rotated files kept as `project_fetcher.log.1` (newest) and up.
+static LOG_ROTATE_KEEP: AtomicUsize = AtomicUsize::new(DEFAULT_LOG_ROTATE_KEEP);
+
+pub fn set_log_rotation(size_mb: u64, keep: usize) {
+    LOG_ROTATE_BYTES.store(size_mb.saturating_mul(1024 * 1024), Ordering::Relaxed);
+    LOG_ROTATE_KEEP.store(keep, Ordering::Relaxed);
+}
+
+/// Lines kept from each end of captured command output; 0 keeps everything.
+static MAX_OUTPUT_LINES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_max_output_lines(max_lines: usize) {
+    MAX_OUTPUT_LINES.store(max_lines, Ordering::Relaxed);
+}
+
+/// Whether `--verbose` echoes log lines to stderr besides the log target.
+static ECHO_STDERR: AtomicBool = AtomicBool::new(false);
+
+pub fn set_echo_stderr(enabled: bool) {
+    ECHO_STDERR.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether `--trace` logs every subprocess the tool runs.
+static TRACE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_trace(enabled: bool) {
+    TRACE.store(enabled, Ordering::Relaxed);
+}
+
+/// With `--trace`, logs a finished subprocess as a `[TRACE]` line holding a
+/// JSON object. `command` must already be redacted. `exit_code` is `None`
+/// when the process failed to start or was killed by a signal; `cwd` is
+/// `None` when it ran in the tool's own working directory.
+pub fn log_trace(command: &str, cwd: Option<&Path>, exit_code: Option<i32>, duration: Duration) {
+    if !TRACE.load(Ordering::Relaxed) {
+        return;
+    }
+    let cwd = match cwd {
+        Some(cwd) => cwd.display().to_string(),
+        None => std::env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_else(|_| ".".to_string()),
+    };
+    let entry = serde_json::json!({
+        "command": command,
+        "cwd": cwd,
+        "exit_code": exit_code,
+        "duration_ms": duration.as_millis(),
+    });
+    log_to_file(LogLevel::Trace, &entry.to_string());
+}
+
+/// Shortens captured command output to its first and last `--max-output-lines`
+/// lines before it is logged, marking how much was left out.
+pub fn truncate_output(output: &str) -> Cow<'_, str> {
+    let max_lines = MAX_OUTPUT_LINES.load(Ordering::Relaxed);
+    let line_count = output.lines().count();
+    if max_lines == 0 || line_count <= max_lines * 2 {
+        return Cow::Borrowed(output);
+    }
+
+    let lines: Vec<&str> = output.lines().collect();
+    let omitted = line_count - max_lines * 2;
+    Cow::Owned(format!(
+        "{}\n... ({} lines omitted) ...\n{}",
+        lines[..max_lines].join("\n"),
+        omitted,
+        lines[line_count - max_lines..].join("\n")
+    ))
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum LogLevel {
     Info,
     Success,
     Error,
     Warning,
+    Trace,
 }
 
 impl LogLevel {
-    fn to_prefix(&self) -> &'static str {
+    fn name(self) -> &'static str {
+        match self {
+            LogLevel::Info => "info",
+            LogLevel::Success => "success",
+            LogLevel::Error => "error",
+            LogLevel::Warning => "warning",
+            LogLevel::Trace => "trace",
+        }
+    }
+
+    fn to_prefix(self) -> &'static str {
         match self {
             LogLevel::Info => "[INFO]",
             LogLevel::Success => "[SUCCESS]",
             LogLevel::Error => "[ERROR]",
             LogLevel::Warning => "[WARN]",
+            LogLevel::Trace => "[TRACE]",
         }
     }
 }
 
+/// Where log lines go (`--log-target`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogTarget {
+    /// Append to the log file (`project_fetcher.log` in the working
+    /// directory unless `--log-file` or `log_file` say otherwise).
+    #[default]
+    File,
+    /// Send to the local syslog daemon (picked up by journald on systemd).
+    Syslog,
+    Stderr,
+}
+
+impl LogTarget {
+    /// Where to tell the user to look for details.
+    pub fn describe(self) -> String {
+        match self {
+            LogTarget::File => log_file_path().display().to_string(),
+            LogTarget::Syslog => "syslog".to_string(),
+            LogTarget::Stderr => "the log output above".to_string(),
+        }
+    }
+}
+
+/// A destination for log lines.
+trait LogSink: Send {
+    fn write(&mut self, level: LogLevel, message: &str);
+}
+
+/// How lines are written to the log file and stderr (`log_format`).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// `[timestamp] [LEVEL] message`.
+    #[default]
+    Text,
+    /// One JSON object per line with `timestamp`, `level`, `project` (when
+    /// known) and `message`.
+    Json,
+}
+
+/// Whether lines are written as JSON objects rather than text.
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_log_format(format: LogFormat) {
+    JSON_FORMAT.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// One line of the log in `LogFormat::Json`.
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    timestamp: String,
+    level: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+    message: &'a str,
+}
+
+/// Formats a line the way the log file and stderr show it. JSON lines name
+/// the project the calling thread is working on, if any.
+fn format_line(level: LogLevel, message: &str) -> String {
+    if JSON_FORMAT.load(Ordering::Relaxed) {
+        let entry = JsonLine {
+            timestamp: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            level: level.name(),
+            project: CURRENT_PROJECT
+                .with(|current| current.borrow().as_ref().map(|c| c.name.clone())),
+            message,
+        };
+        return serde_json::to_string(&entry).unwrap_or_default() + "\n";
+    }
+    format!(
+        "[{}] {} {}\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        level.to_prefix(),
+        message
+    )
+}
+
+struct FileSink;
+
+impl FileSink {
+    /// Shifts the log file to `<name>.1`, `.1` to `.2` and so on once it has
+    /// reached the rotation size, dropping the oldest. Only the file is
+    /// stat'ed; nothing is read.
+    fn rotate_if_needed(path: &Path) {
+        let max_bytes = LOG_ROTATE_BYTES.load(Ordering::Relaxed);
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.len() >= max_bytes => {}
+            _ => return,
+        }
+        let keep = LOG_ROTATE_KEEP.load(Ordering::Relaxed);
+        let rotated = |index: usize| {
+            let mut rotated = path.as_os_str().to_owned();
+            rotated.push(format!(".{}", index));
+            PathBuf::from(rotated)
+        };
+        let result = if keep == 0 {
+            fs::remove_file(path)
+        } else {
+            let _ = fs::remove_file(rotated(keep));
+            for index in (1..keep).rev() {
+                let _ = fs::rename(rotated(index), rotated(index + 1));
+            }
+            fs::rename(path, rotated(1))
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to rotate log file {}: {}", path.display(), e);
+        }
+    }
+
+    /// Appends `line` to the log file at `path`, rotating it first if due.
+    fn append(path: &Path, line: &str) {
+        Self::rotate_if_needed(path);
+        if let Ok(mut file) = OpenOptions::new().append(true).create(true).open(path) {
+            let _ = file.write_all(line.as_bytes());
+        } else {
+            eprint!(
+                "Failed to open or create log file: {}. Message: {}",
+                path.display(),
+                line
+            );
+        }
+    }
+}
+
+impl LogSink for FileSink {
+    fn write(&mut self, level: LogLevel, message: &str) {
+        Self::append(&log_file_path(), &format_line(level, message));
+    }
+}
+
+struct StderrSink;
+
+impl LogSink for StderrSink {
+    fn write(&mut self, level: LogLevel, message: &str) {
+        let _ = io::stderr().write_all(format_line(level, message).as_bytes());
+    }
+}
+
+#[cfg(unix)]
+struct SyslogSink(syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>);
+
+#[cfg(unix)]
+impl LogSink for SyslogSink {
+    /// Syslog stamps the time itself, and the level maps to a severity.
+    fn write(&mut self, level: LogLevel, message: &str) {
+        let logger = &mut self.0;
+        let _ = match level {
+            LogLevel::Error => logger.err(message),
+            LogLevel::Warning => logger.warning(message),
+            LogLevel::Success => logger.notice(message),
+            LogLevel::Info => logger.info(message),
+            LogLevel::Trace => logger.debug(message),
+        };
+    }
+}
+
+/// The active sink; `None` until `--log-target` picks one, which means the
+/// log file. The lock also keeps lines from parallel workers from
+/// interleaving.
+static SINK: Mutex<Option<Box<dyn LogSink>>> = Mutex::new(None);
+
+/// Sends all further log lines to `target`. Fails when syslog cannot be
+/// reached.
+pub fn set_log_target(target: LogTarget) -> io::Result<()> {
+    let sink: Box<dyn LogSink> = match target {
+        LogTarget::File => Box::new(FileSink),
+        LogTarget::Stderr => Box::new(StderrSink),
+        LogTarget::Syslog => syslog_sink()?,
+    };
+    *SINK.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(sink);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn syslog_sink() -> io::Result<Box<dyn LogSink>> {
+    let formatter = syslog::Formatter3164 {
+        facility: syslog::Facility::LOG_USER,
+        hostname: None,
+        process: env!("CARGO_PKG_NAME").to_string(),
+        pid: std::process::id(),
+    };
+    let logger = syslog::unix(formatter).map_err(|e| {
+        let causes: Vec<String> = e.iter().map(ToString::to_string).collect();
+        io::Error::other(causes.join(": "))
+    })?;
+    Ok(Box::new(SyslogSink(logger)))
+}
+
+#[cfg(not(unix))]
+fn syslog_sink() -> io::Result<Box<dyn LogSink>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "syslog is only available on Unix",
+    ))
+}
+
 pub fn log_to_file(level: LogLevel, message: &str) {
-    if let Ok(mut file) = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(LOG_FILE_NAME)
-    {
-        let _ = writeln!(
-            file,
-            "[{}] {} {}",
-            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-            level.to_prefix(),
-            message
-        );
-    } else {
-        eprintln!(
-            "Failed to open or create log file: {}. Message: [{}] {} {}",
-            LOG_FILE_NAME,
-            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-            level.to_prefix(),
-            message
-        );
-    }
-}
\ No newline at end of file
+    let project_log =
+        CURRENT_PROJECT.with(|current| current.borrow().as_ref().and_then(|c| c.log_path.clone()));
+    if let Some(path) = &project_log {
+        FileSink::append(path, &format_line(level, message));
+        if PROJECT_LOG_ONLY.load(Ordering::Relaxed) {
+            if ECHO_STDERR.load(Ordering::Relaxed) {
+                StderrSink.write(level, message);
+            }
+            return;
+        }
+    }
+    let mut sink = SINK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    sink.get_or_insert_with(|| Box::new(FileSink))
+        .write(level, message);
+    if ECHO_STDERR.load(Ordering::Relaxed) {
+        StderrSink.write(level, message);
+    }
+}
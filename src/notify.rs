@@ -0,0 +1,108 @@
+use crate::config::NotifyEmailConfig;
+use crate::logger::{log_to_file, log_trace, LogLevel};
+use crate::redact::redact_text;
+use crate::report::{ProjectOutcome, ProjectStatus};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+const DEFAULT_SENDMAIL_COMMAND: &str = "sendmail";
+const DEFAULT_SUBJECT: &str = "git-project-updater run summary";
+
+pub fn send_summary_email(notify_config: &NotifyEmailConfig, outcomes: &[ProjectOutcome]) {
+    if notify_config.to.is_empty() {
+        log_to_file(
+            LogLevel::Warning,
+            "notify_email has no recipients configured; skipping summary email.",
+        );
+        return;
+    }
+
+    let message = compose_message(notify_config, outcomes);
+    let sendmail_command = notify_config
+        .sendmail_command
+        .as_deref()
+        .unwrap_or(DEFAULT_SENDMAIL_COMMAND);
+
+    match deliver(sendmail_command, &message) {
+        Ok(()) => log_to_file(
+            LogLevel::Success,
+            &format!("Sent run summary email to {}.", notify_config.to.join(", ")),
+        ),
+        Err(e) => log_to_file(
+            LogLevel::Warning,
+            &format!(
+                "Failed to send run summary email via '{}': {}",
+                sendmail_command, e
+            ),
+        ),
+    }
+}
+
+fn compose_message(notify_config: &NotifyEmailConfig, outcomes: &[ProjectOutcome]) -> String {
+    let failed_projects: Vec<&ProjectOutcome> = outcomes
+        .iter()
+        .filter(|o| o.status == ProjectStatus::Error)
+        .collect();
+    let subject = notify_config.subject.as_deref().unwrap_or(DEFAULT_SUBJECT);
+    let status = if failed_projects.is_empty() {
+        "OK"
+    } else {
+        "FAILED"
+    };
+
+    let mut message = format!("To: {}\n", notify_config.to.join(", "));
+    if let Some(from) = &notify_config.from {
+        message.push_str(&format!("From: {}\n", from));
+    }
+    message.push_str(&format!("Subject: [{}] {}\n\n", status, subject));
+    message.push_str(&format!(
+        "Projects processed: {}\nSucceeded: {}\nFailed: {}\n",
+        outcomes.len(),
+        outcomes.len() - failed_projects.len(),
+        failed_projects.len()
+    ));
+
+    if !failed_projects.is_empty() {
+        message.push_str("\nFailed projects:\n");
+        for outcome in failed_projects {
+            message.push_str(&format!(
+                "- {}: {}\n",
+                outcome.project,
+                redact_text(outcome.error.as_deref().unwrap_or_default())
+            ));
+        }
+    }
+    message
+}
+
+fn deliver(sendmail_command: &str, message: &str) -> std::io::Result<()> {
+    let started = Instant::now();
+    let mut child = Command::new(sendmail_command)
+        .arg("-t")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(message.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    log_trace(
+        &format!("{} -t", sendmail_command),
+        None,
+        output.status.code(),
+        started.elapsed(),
+    );
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!(
+            "exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
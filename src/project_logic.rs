@@ -1,26 +1,349 @@
-use crate::config::ProjectConfig;
-use crate::error::{ProjectError};
+#[cfg(feature = "archive")]
+use crate::archive::{default_archive_url, import_from_archive};
+use crate::config::{ArchiveFallback, EmptyPullBranches, GlobalConfig, ProjectConfig};
+use crate::display::shorten_path;
+use crate::error::{GitError, ProjectError};
 use crate::git_utils::{
-    checkout_branch, clone_repo, get_current_branch, is_git_repo, pull_branch_updates,
+    abbreviate_hash, abort_unfinished_merge, announce_dry_run, check_integrity,
+    check_remote_reachable, checkout_branch, checkout_tag, clean_worktree, clone_repo,
+    deepen_history, diff_shortstat, ensure_remote_exists, ensure_single_branch_tracked,
+    fetch_remote, get_ahead_behind, get_config_value, get_current_branch, get_head_commit,
+    get_remote_default_branch, get_remote_url, get_submodule_pins, get_tag_commit,
+    has_uncommitted_changes, is_dry_run, is_empty_repo, is_git_repo, is_merge_conflict,
+    is_shallow_clone, is_working_tree_clean, list_branch_heads, list_commits_between,
+    list_remote_branches, prune_remote, pull_branch_updates, remote_shares_history,
+    reset_hard_to_remote, resolve_commit, restore_branch_state, run_gc, set_initial_branch,
+    set_remote_url, stash_changes, sync_bare_clone, unshallow_history, update_submodules,
+    verify_head_signature, BranchState, CloneOptions, GitContext, PullOptions,
+    ARCHIVE_SOURCE_CONFIG_KEY,
 };
+use crate::hooks::run_hook;
+use crate::lock::ProjectLock;
 use crate::logger::{log_to_file, LogLevel};
-use indicatif::ProgressBar;
-use shellexpand;
+use crate::redact::redact_url;
+use crate::report::{BranchUpdate, PostStatus, ProjectOutcome, SubmoduleChange};
+use globset::Glob;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
-pub fn process_project(
-    config: &ProjectConfig,
-    parent_clone_dir: &Path,
-    progress_bar: &ProgressBar,
-) -> Result<(), ProjectError> {
+/// Whether `--force` stashes local changes instead of skipping the pull.
+static FORCE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_force(enabled: bool) {
+    FORCE.store(enabled, Ordering::Relaxed);
+}
+
+/// `--gc`: run a full `git gc` in every repository instead of `--auto`.
+static FULL_GC: AtomicBool = AtomicBool::new(false);
+
+pub fn set_full_gc(enabled: bool) {
+    FULL_GC.store(enabled, Ordering::Relaxed);
+}
+
+/// `--reset-on-conflict`: reset every project hard to its remote branch when
+/// a pull hits a merge conflict, as if each set `reset_on_conflict`.
+static RESET_ON_CONFLICT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_reset_on_conflict(enabled: bool) {
+    RESET_ON_CONFLICT.store(enabled, Ordering::Relaxed);
+}
+
+/// `--update-remotes`: point an existing clone's remote at the configured
+/// `url` when they differ.
+static UPDATE_REMOTES: AtomicBool = AtomicBool::new(false);
+
+pub fn set_update_remotes(enabled: bool) {
+    UPDATE_REMOTES.store(enabled, Ordering::Relaxed);
+}
+
+/// Resolves a project's `path` against the clone parent directory, expanding
+/// `~` and normalising `.`/`..` components lexically.
+pub fn resolve_project_path(config: &ProjectConfig, parent_clone_dir: &Path) -> PathBuf {
     let expanded_project_path_str = shellexpand::tilde(&config.path).to_string();
     let project_path = if Path::new(&expanded_project_path_str).is_absolute() {
         PathBuf::from(expanded_project_path_str)
     } else {
-        parent_clone_dir.join(&expanded_project_path_str) // Added &
+        parent_clone_dir.join(&expanded_project_path_str)
+    };
+    normalize_path(&project_path)
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Resolves every project path up front and rejects those that would clone
+/// into the parent directory itself or into an ancestor of another project,
+/// where a clone would clobber its siblings. Results follow config order.
+pub fn preflight_project_paths(
+    projects: &[ProjectConfig],
+    parent_clone_dir: &Path,
+) -> Vec<Result<PathBuf, ProjectError>> {
+    let parent_clone_dir = normalize_path(parent_clone_dir);
+    let resolved: Vec<PathBuf> = projects
+        .iter()
+        .map(|project| resolve_project_path(project, &parent_clone_dir))
+        .collect();
+
+    projects
+        .iter()
+        .zip(&resolved)
+        .enumerate()
+        .map(|(index, (project, path))| {
+            let unsafe_path = |reason: String| ProjectError::UnsafePath {
+                project_name: project.project.clone(),
+                path: path.clone(),
+                reason,
+            };
+            if *path == parent_clone_dir {
+                return Err(unsafe_path(
+                    "it is the clone parent directory itself".to_string(),
+                ));
+            }
+            if let Some((other, _)) = projects
+                .iter()
+                .zip(&resolved)
+                .enumerate()
+                .find(|(other_index, (_, other_path))| {
+                    *other_index != index && other_path.starts_with(path) && *other_path != path
+                })
+                .map(|(_, pair)| pair)
+            {
+                return Err(unsafe_path(format!(
+                    "it contains the path of project '{}'",
+                    other.project
+                )));
+            }
+            Ok(path.clone())
+        })
+        .collect()
+}
+
+/// `--preflight`: runs `git ls-remote` against every project's remote, up to
+/// `jobs` at once, so a dead VPN or host shows up before anything is cloned.
+/// Projects sharing a URL and `ssh_key` are asked once. Returns why each
+/// unreachable project failed, in config order.
+pub fn check_remotes_reachable(projects: &[ProjectConfig], jobs: usize) -> Vec<Option<String>> {
+    let mut remotes: Vec<(&str, Option<PathBuf>)> = Vec::new();
+    let remote_of: Vec<usize> = projects
+        .iter()
+        .map(|config| {
+            let remote = (config.url.as_str(), config.ssh_key_path());
+            remotes
+                .iter()
+                .position(|known| *known == remote)
+                .unwrap_or_else(|| {
+                    remotes.push(remote);
+                    remotes.len() - 1
+                })
+        })
+        .collect();
+
+    let next = AtomicUsize::new(0);
+    let failures: Mutex<Vec<Option<String>>> = Mutex::new(vec![None; remotes.len()]);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, remotes.len().max(1)) {
+            scope.spawn(|| {
+                let progress_bar = ProgressBar::hidden();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((url, ssh_key)) = remotes.get(index) else {
+                        break;
+                    };
+                    let owner = &projects[remote_of
+                        .iter()
+                        .position(|&remote| remote == index)
+                        .unwrap_or_default()];
+                    let git_ctx = GitContext {
+                        project_name: &owner.project,
+                        progress_bar: &progress_bar,
+                        success_exit_codes: &[],
+                        ssh_key: ssh_key.as_deref(),
+                        remote: owner.remote_name(),
+                    };
+                    if let Err(e) = check_remote_reachable(&git_ctx, url) {
+                        let mut failures = failures
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner());
+                        failures[index] = Some(git_error_summary(&e));
+                    }
+                }
+            });
+        }
+    });
+    let failures = failures
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    remote_of
+        .iter()
+        .map(|&remote| failures[remote].clone())
+        .collect()
+}
+
+/// The first line git printed for a failed command, which names the problem
+/// without the full command and output of the error itself.
+fn git_error_summary(error: &GitError) -> String {
+    match error {
+        GitError::CommandFailed { stdout, stderr, .. } => stderr
+            .lines()
+            .chain(stdout.lines())
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string),
+        _ => None,
+    }
+    .unwrap_or_else(|| error.to_string())
+}
+
+/// For projects with `move_on_path_change` whose directory does not exist
+/// yet, looks for a clone of the same remote among the sibling directories
+/// and moves it into place instead of letting the run clone again. Paths of
+/// other configured projects are never taken, and nothing is moved when
+/// several candidates match.
+pub fn relocate_moved_clones(
+    projects: &[ProjectConfig],
+    path_checks: &[Result<PathBuf, ProjectError>],
+) {
+    let configured: Vec<&Path> = path_checks
+        .iter()
+        .filter_map(|check| check.as_ref().ok().map(PathBuf::as_path))
+        .collect();
+    let progress_bar = ProgressBar::hidden();
+
+    for (config, path_check) in projects.iter().zip(path_checks) {
+        let Ok(project_path) = path_check else {
+            continue;
+        };
+        if !config.move_on_path_change.unwrap_or(false) || project_path.exists() {
+            continue;
+        }
+        let Some(parent) = project_path.parent() else {
+            continue;
+        };
+        let Ok(entries) = fs::read_dir(parent) else {
+            continue;
+        };
+        let ssh_key = config.ssh_key_path();
+        let git_ctx = GitContext {
+            project_name: &config.project,
+            progress_bar: &progress_bar,
+            success_exit_codes: &[],
+            ssh_key: ssh_key.as_deref(),
+            remote: config.remote_name(),
+        };
+        let candidates: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|candidate| {
+                is_git_repo(candidate) && !configured.contains(&candidate.as_path())
+            })
+            .filter(|candidate| {
+                get_remote_url(&git_ctx, candidate, git_ctx.remote)
+                    .is_ok_and(|url| same_remote(&url, &config.url))
+            })
+            .collect();
+
+        match candidates.as_slice() {
+            [] => {}
+            [old_path] if is_dry_run() => announce_dry_run(
+                &progress_bar,
+                &format!(
+                    "{}: would move existing clone '{}' to '{}' (move_on_path_change)",
+                    config.project,
+                    old_path.display(),
+                    project_path.display()
+                ),
+            ),
+            [old_path] => match fs::rename(old_path, project_path) {
+                Ok(()) => log_to_file(
+                    LogLevel::Success,
+                    &format!(
+                        "Project '{}': Moved existing clone '{}' to the new path '{}' instead of cloning again.",
+                        config.project,
+                        old_path.display(),
+                        project_path.display()
+                    ),
+                ),
+                Err(e) => log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "Project '{}': Could not move existing clone '{}' to '{}'; cloning instead. Error: {}",
+                        config.project,
+                        old_path.display(),
+                        project_path.display(),
+                        e
+                    ),
+                ),
+            },
+            _ => {
+                let listed: Vec<String> = candidates.iter().map(|path| path.display().to_string()).collect();
+                log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "Project '{}': Several clones of '{}' could be moved to the new path ({}); cloning instead.",
+                        config.project,
+                        redact_url(&config.url),
+                        listed.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Whether two remote URLs name the same repository, ignoring a trailing
+/// `/` or `.git`.
+fn same_remote(a: &str, b: &str) -> bool {
+    let normalize = |url: &str| {
+        let url = url.trim_end_matches('/');
+        url.strip_suffix(".git").unwrap_or(url).to_string()
+    };
+    normalize(a) == normalize(b)
+}
+
+/// Clones or updates one project. `remote_done` is called once nothing more
+/// is fetched from its remote, before the local checks, hooks and gc, so
+/// its `max_concurrent_per_host` slot can go to the next project.
+pub fn process_project(
+    config: &ProjectConfig,
+    global_config: &GlobalConfig,
+    parent_clone_dir: &Path,
+    progress_bar: &ProgressBar,
+    multi_progress: &MultiProgress,
+    remote_done: &dyn Fn(),
+) -> Result<ProjectOutcome, ProjectError> {
+    let mut outcome = ProjectOutcome::new(&config.project);
+    let ssh_key = config.ssh_key_path();
+    let git_ctx = GitContext {
+        project_name: &config.project,
+        progress_bar,
+        success_exit_codes: config
+            .success_exit_codes
+            .as_deref()
+            .or(global_config.success_exit_codes.as_deref())
+            .unwrap_or_default(),
+        ssh_key: ssh_key.as_deref(),
+        remote: config.remote_name(),
     };
 
+    let project_path = resolve_project_path(config, parent_clone_dir);
+
     let initial_msg = format!(
         "Checking project: '{}' at '{}'",
         config.project,
@@ -28,17 +351,63 @@ pub fn process_project(
     );
     log_to_file(LogLevel::Info, &initial_msg);
 
+    // Dry runs change nothing, so they neither need nor leave a lock file.
+    let _project_lock = if is_dry_run() {
+        None
+    } else {
+        match ProjectLock::try_acquire(&project_path) {
+            Ok(Some(lock)) => Some(lock),
+            Ok(None) => {
+                let skip_msg = format!(
+                    "Project '{}': Skipped because another run is working on '{}'.",
+                    config.project,
+                    project_path.display()
+                );
+                log_to_file(LogLevel::Warning, &skip_msg);
+                outcome.skipped = Some(skip_msg);
+                return Ok(outcome);
+            }
+            Err(source) => {
+                return Err(ProjectError::Lock {
+                    project_name: config.project.clone(),
+                    path: project_path,
+                    source,
+                })
+            }
+        }
+    };
+
+    if config.reclone_on_url_change.unwrap_or(false) && is_git_repo(&project_path) {
+        if is_dry_run() {
+            announce_dry_run(
+                progress_bar,
+                &format!(
+                    "{}: would check whether the remote was replaced (reclone_on_url_change)",
+                    config.project
+                ),
+            );
+        } else {
+            backup_if_remote_replaced(config, &git_ctx, &project_path)?;
+        }
+    }
+
     if !project_path.exists() {
-        let msg = format!(
+        progress_bar.set_message(format!(
             "Project directory '{}' for '{}' not found. Attempting to clone.",
-            project_path.display(),
+            shorten_path(&project_path),
             config.project
+        ));
+        log_to_file(
+            LogLevel::Info,
+            &format!(
+                "Project directory '{}' for '{}' not found. Attempting to clone.",
+                project_path.display(),
+                config.project
+            ),
         );
-        progress_bar.set_message(msg.clone());
-        log_to_file(LogLevel::Info, &msg);
 
         if let Some(parent) = project_path.parent() {
-            if !parent.exists() {
+            if !parent.exists() && !is_dry_run() {
                 fs::create_dir_all(parent).map_err(|e| ProjectError::CreateDirs {
                     project_name: config.project.clone(),
                     path: parent.to_path_buf(),
@@ -47,191 +416,1502 @@ pub fn process_project(
             }
         }
 
-        clone_repo(
-            progress_bar,
-            &config.project,
-            &config.url,
-            &project_path,
-        )
-        .map_err(|e| {
-            log_to_file(
-                LogLevel::Error,
-                &format!(
-                    "Clone failed for '{}'. Skipping project. Error: {}",
-                    config.project, e
-                ),
+        if config.archive_fallback == Some(ArchiveFallback::Always) {
+            if is_dry_run() {
+                announce_dry_run(
+                    progress_bar,
+                    &format!(
+                        "{}: would import an archive into '{}'",
+                        config.project,
+                        project_path.display()
+                    ),
+                );
+            } else {
+                import_archive(config, &project_path, progress_bar)?;
+            }
+        } else {
+            let clone_result = clone_repo(
+                &git_ctx,
+                &config.url,
+                &project_path,
+                &CloneOptions::from_config(config),
             );
-            ProjectError::GitOperation {
-                project_name: config.project.clone(),
-                source: e,
+            match clone_result {
+                Ok(()) => {}
+                Err(e) if config.archive_fallback == Some(ArchiveFallback::OnFailure) => {
+                    log_to_file(
+                        LogLevel::Warning,
+                        &format!(
+                            "Clone failed for '{}', falling back to archive download. Error: {}",
+                            config.project, e
+                        ),
+                    );
+                    import_archive(config, &project_path, progress_bar)?;
+                }
+                Err(e) => {
+                    log_to_file(
+                        LogLevel::Error,
+                        &format!(
+                            "Clone failed for '{}'. Skipping project. Error: {}",
+                            config.project, e
+                        ),
+                    );
+                    return Err(ProjectError::GitOperation {
+                        project_name: config.project.clone(),
+                        source: e,
+                    });
+                }
             }
-        })?;
+        }
+        outcome.cloned = true;
+        if is_dry_run() {
+            // Nothing was cloned, so there is no repository to inspect further.
+            return Ok(outcome);
+        }
     } else {
-        let msg = format!(
+        progress_bar.set_message(format!(
             "Project directory '{}' for '{}' already exists.",
-            project_path.display(),
+            shorten_path(&project_path),
             config.project
+        ));
+        log_to_file(
+            LogLevel::Info,
+            &format!(
+                "Project directory '{}' for '{}' already exists.",
+                project_path.display(),
+                config.project
+            ),
         );
-        progress_bar.set_message(msg.clone());
-        log_to_file(LogLevel::Info, &msg);
     }
 
-    let check_repo_msg = format!(
+    progress_bar.set_message(format!(
         "Verifying Git repository at '{}'...",
-        project_path.display()
+        shorten_path(&project_path)
+    ));
+    log_to_file(
+        LogLevel::Info,
+        &format!(
+            "Verifying Git repository at '{}'...",
+            project_path.display()
+        ),
     );
-    progress_bar.set_message(check_repo_msg.clone());
-    log_to_file(LogLevel::Info, &check_repo_msg);
 
     if !is_git_repo(&project_path) {
-        let err_msg = format!(
+        progress_bar.set_message(format!(
             "'{}' is not a git repository. Skipping further git operations.",
-            project_path.display()
+            shorten_path(&project_path)
+        ));
+        log_to_file(
+            LogLevel::Warning,
+            &format!(
+                "'{}' is not a git repository. Skipping further git operations.",
+                project_path.display()
+            ),
         );
-        progress_bar.set_message(err_msg.clone());
-        log_to_file(LogLevel::Warning, &err_msg);
         // This specific case is more of a warning and skip, rather than a hard error for the whole app.
         // Depending on strictness, one might return an error here.
         // For now, log and return Ok to allow other projects to process.
         return Err(ProjectError::NotGitRepository {
-             project_name: config.project.clone(),
-             path: project_path.to_path_buf()
+            project_name: config.project.clone(),
+            path: project_path.to_path_buf(),
         });
     }
 
-    // --- Git Pull Section ---
-    if let Some(branches_to_pull) = &config.pull_branches {
-        if !branches_to_pull.is_empty() {
-            let original_branch = match get_current_branch(&project_path, &config.project) {
-                Ok(branch) => {
+    if let Ok(true) = is_empty_repo(&git_ctx, &project_path) {
+        if let Some(init_branch) = &config.init_branch {
+            if let Err(e) = set_initial_branch(&git_ctx, &project_path, init_branch) {
+                log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "Project '{}': Could not set initial branch '{}': {}",
+                        config.project, init_branch, e
+                    ),
+                );
+            }
+        }
+        let empty_msg = format!(
+            "Project '{}': Repository has no commits yet. Skipping update.",
+            config.project
+        );
+        log_to_file(LogLevel::Info, &empty_msg);
+        if !outcome.cloned {
+            outcome.skipped = Some(empty_msg);
+        }
+        return Ok(outcome);
+    }
+
+    // Also on the run that imported it: the archive has no remote to check
+    // or pull from.
+    if config.archive_fallback.is_some() {
+        if let Ok(Some(archive_source)) =
+            get_config_value(&git_ctx, &project_path, ARCHIVE_SOURCE_CONFIG_KEY)
+        {
+            if !outcome.cloned {
+                let skip_msg = format!(
+                    "Project '{}': Imported from archive '{}', which has no upstream history. Skipping update.",
+                    config.project, archive_source
+                );
+                log_to_file(LogLevel::Info, &skip_msg);
+                outcome.skipped = Some(skip_msg);
+            }
+            return Ok(outcome);
+        }
+    }
+
+    ensure_remote_exists(&git_ctx, &project_path).map_err(|e| ProjectError::GitOperation {
+        project_name: config.project.clone(),
+        source: e,
+    })?;
+
+    // Only after the reclone_on_url_change check above: repointing first
+    // would make the remote match the config and hide an unrelated history.
+    if UPDATE_REMOTES.load(Ordering::Relaxed) && !outcome.cloned {
+        update_remote_url(config, &git_ctx, &project_path);
+    }
+
+    if let Some(branch) = config.single_branch.as_deref().filter(|_| !outcome.cloned) {
+        ensure_single_branch_tracked(&git_ctx, &project_path, branch).map_err(|e| {
+            ProjectError::GitOperation {
+                project_name: config.project.clone(),
+                source: e,
+            }
+        })?;
+    }
+
+    if let Some(pre_update) = &config.pre_update {
+        if is_dry_run() {
+            announce_dry_run(
+                progress_bar,
+                &format!(
+                    "{}: would run pre_update hook '{}'",
+                    config.project, pre_update
+                ),
+            );
+        } else if let Err(e) = run_hook(&project_path, pre_update, &config.project, progress_bar) {
+            if config.fail_on_pre_update_error.unwrap_or(false) {
+                return Err(e);
+            }
+            let skip_msg = format!(
+                "Project '{}': Skipping update because the pre_update hook failed. Error: {}",
+                config.project, e
+            );
+            progress_bar.set_message(format!("{} - pre_update failed, skipped", config.project));
+            log_to_file(LogLevel::Warning, &skip_msg);
+            outcome.skipped = Some(e.to_string());
+            return Ok(outcome);
+        }
+    }
+
+    // Neither fetch_only nor bare clones touch a work tree.
+    let leaves_work_tree = config.fetch_only.unwrap_or(false) || config.is_bare();
+    if reset_on_conflict(config) && !leaves_work_tree && !outcome.cloned {
+        if let Err(e) = abort_unfinished_merge(&git_ctx, &project_path) {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not abort an unfinished merge: {}",
+                    config.project, e
+                ),
+            );
+        }
+    }
+    // clean_worktree discards local edits after the pull anyway.
+    if !config.clean_worktree.unwrap_or(false) && !leaves_work_tree {
+        match is_working_tree_clean(&git_ctx, &project_path) {
+            Ok(true) => {}
+            Ok(false) if FORCE.load(Ordering::Relaxed) => {
+                stash_changes(&git_ctx, &project_path).map_err(|e| ProjectError::GitOperation {
+                    project_name: config.project.clone(),
+                    source: e,
+                })?;
+            }
+            Ok(false) => {
+                let skip_msg = format!(
+                    "Project '{}': Working tree has uncommitted changes. Skipping pull (use --force to stash them first).",
+                    config.project
+                );
+                progress_bar
+                    .set_message(format!("{} - uncommitted changes, skipped", config.project));
+                log_to_file(LogLevel::Warning, &skip_msg);
+                outcome.skipped = Some(skip_msg);
+                return Ok(outcome);
+            }
+            Err(e) => log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not check for local changes: {}",
+                    config.project, e
+                ),
+            ),
+        }
+    }
+
+    if config.depth == Some(0) && !outcome.cloned {
+        match is_shallow_clone(&git_ctx, &project_path) {
+            Ok(true) => {
+                if let Err(e) = unshallow_history(&git_ctx, &project_path) {
                     log_to_file(
-                        LogLevel::Info,
+                        LogLevel::Warning,
                         &format!(
-                            "Project '{}': Current branch is '{}'.",
-                            config.project, branch
+                            "Project '{}': Could not fetch full history: {}",
+                            config.project, e
                         ),
                     );
-                    Some(branch)
-                }
-                Err(e) => {
-                    let err_msg = format!(
-                        "Project '{}': Could not determine current branch. Error: {}. Will proceed without restoring branch.",
-                        config.project, e
-                    );
-                    progress_bar.set_message(format!("{} - Branch check failed", config.project));
-                    log_to_file(LogLevel::Warning, &err_msg);
-                    None
-                }
-            };
-
-            for branch_name in branches_to_pull {
-                progress_bar.set_message(format!(
-                    "{} - Switching to branch {}",
-                    config.project, branch_name
-                ));
-                match checkout_branch(
-                    &project_path,
-                    branch_name,
-                    &config.project,
-                    progress_bar,
-                ) {
-                    Ok(_) => {
-                        if let Err(e) = pull_branch_updates(
-                            &project_path,
-                            Some(branch_name),
-                            &config.project,
-                            progress_bar,
-                        ) {
-                            log_to_file(
-                                LogLevel::Warning,
-                                &format!(
-                                    "Project '{}': Continuing after pull error on branch {}: {}",
-                                    config.project, branch_name, e
-                                ),
-                            );
-                        }
-                    }
-                    Err(e) => {
-                        let err_msg = format!(
-                            "Project '{}': Failed to checkout branch '{}'. Skipping pull for this branch. Error: {}",
-                            config.project, branch_name, e
-                        );
-                        progress_bar.set_message(format!(
-                            "{} - Checkout failed: {}",
-                            config.project, branch_name
-                        ));
-                        log_to_file(LogLevel::Error, &err_msg);
-                    }
                 }
             }
+            Ok(false) => {}
+            Err(e) => log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not check for a shallow clone: {}",
+                    config.project, e
+                ),
+            ),
+        }
+    }
 
-            if let Some(orig_branch_name) = original_branch {
-                // Check if current branch is different from original, or if original wasn't in pull_branches list
-                let current_branch_after_pulls = get_current_branch(&project_path, &config.project).ok();
-                if current_branch_after_pulls.as_deref() != Some(&orig_branch_name) {
-                    log_to_file(LogLevel::Info, &format!("Project '{}': Attempting to restore original branch '{}'.", config.project, orig_branch_name));
-                    progress_bar.set_message(format!(
-                        "{} - Restoring original branch {}",
-                        config.project, orig_branch_name
-                    ));
-                    if let Err(e) = checkout_branch(
-                        &project_path,
-                        &orig_branch_name,
-                        &config.project,
-                        progress_bar,
-                    ) {
-                        let err_msg = format!(
-                            "Project '{}': Failed to restore original branch '{}'. Error: {}",
-                            config.project, orig_branch_name, e
-                        );
-                        progress_bar.set_message(format!(
-                            "{} - Restore failed: {}",
-                            config.project, orig_branch_name
-                        ));
-                        log_to_file(LogLevel::Warning, &err_msg);
-                    }
-                } else {
-                     log_to_file(LogLevel::Info, &format!("Project '{}': Already on original branch '{}' or no restoration needed.", config.project, orig_branch_name));
+    if let Some(commits) = config.deepen {
+        match is_shallow_clone(&git_ctx, &project_path) {
+            Ok(true) => {
+                if let Err(e) = deepen_history(&git_ctx, &project_path, commits) {
+                    log_to_file(
+                        LogLevel::Warning,
+                        &format!(
+                            "Project '{}': Could not deepen history: {}",
+                            config.project, e
+                        ),
+                    );
                 }
             }
-        } else {
-            let current_branch_for_log =
-                get_current_branch(&project_path, &config.project).unwrap_or_else(|_| "current".to_string());
-            log_to_file(
+            Ok(false) => log_to_file(
                 LogLevel::Info,
                 &format!(
-                    "Project '{}': pull_branches is empty, pulling {} branch.",
-                    config.project, current_branch_for_log
+                    "Project '{}': deepen is set but the clone is not shallow; nothing to do.",
+                    config.project
                 ),
-            );
-            if let Err(e) =
-                pull_branch_updates(&project_path, None, &config.project, progress_bar)
-            {
-                log_to_file(
-                    LogLevel::Warning,
-                    &format!("Project '{}': Continuing after pull error on current branch: {}", config.project, e),
-                );
-            }
+            ),
+            Err(e) => log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not check for a shallow clone: {}",
+                    config.project, e
+                ),
+            ),
         }
+    }
+
+    let init_submodules = !leaves_work_tree
+        && config
+            .init_submodules
+            .unwrap_or(config.submodule_paths.is_some());
+    let submodules_before = if init_submodules {
+        get_submodule_pins(&git_ctx, &project_path).ok()
     } else {
-        let current_branch_for_log =
-            get_current_branch(&project_path, &config.project).unwrap_or_else(|_| "current".to_string());
-        log_to_file(
-            LogLevel::Info,
-            &format!(
-                "Project '{}': no pull_branches specified, pulling {} branch.",
-                config.project, current_branch_for_log
+        None
+    };
+
+    // Bare clones prune as part of their sync, and fresh clones have
+    // nothing stale yet.
+    if config.prune.or(global_config.prune).unwrap_or(false) && !config.is_bare() && !outcome.cloned
+    {
+        match prune_remote(&git_ctx, &project_path) {
+            Ok(pruned) if !pruned.is_empty() => log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Project '{}': Pruned {} stale remote-tracking branch(es): {}",
+                    config.project,
+                    pruned.len(),
+                    pruned.join(", ")
+                ),
+            ),
+            Ok(_) => {}
+            Err(e) => log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Continuing after pruning error: {}",
+                    config.project, e
+                ),
             ),
+        }
+    }
+
+    if let Some(tag) = &config.checkout_tag {
+        checkout_tag(&git_ctx, &project_path, tag).map_err(|e| ProjectError::GitOperation {
+            project_name: config.project.clone(),
+            source: e,
+        })?;
+    } else if config.is_bare() {
+        sync_bare_and_record(config, &git_ctx, &project_path, &mut outcome)?;
+    } else {
+        pull_configured_branches(
+            config,
+            global_config,
+            &git_ctx,
+            &project_path,
+            multi_progress,
+            &mut outcome,
         );
-        if let Err(e) = pull_branch_updates(&project_path, None, &config.project, progress_bar) {
+    }
+
+    if init_submodules {
+        let submodule_paths = config.submodule_paths.as_deref().unwrap_or_default();
+        if let Err(e) = update_submodules(&git_ctx, &project_path, submodule_paths) {
             log_to_file(
                 LogLevel::Warning,
-                &format!("Project '{}': Continuing after pull error on current branch: {}",config.project,  e),
+                &format!(
+                    "Project '{}': Continuing after submodule update error: {}",
+                    config.project, e
+                ),
             );
+        } else if let (Some(before), Ok(after)) = (
+            submodules_before,
+            get_submodule_pins(&git_ctx, &project_path),
+        ) {
+            outcome.submodule_changes = diff_submodule_pins(&before, &after);
+            for change in &outcome.submodule_changes {
+                log_to_file(
+                    LogLevel::Info,
+                    &format!(
+                        "Project '{}': Submodule '{}' moved {} -> {}",
+                        config.project,
+                        change.path,
+                        change.old.as_deref().unwrap_or("(added)"),
+                        change.new.as_deref().unwrap_or("(removed)")
+                    ),
+                );
+            }
         }
     }
+    remote_done();
 
-    let success_msg = format!("Finished checking/updating project: {}", config.project);
-    log_to_file(LogLevel::Success, &success_msg);
-    Ok(())
-}
\ No newline at end of file
+    if config.clean_worktree.unwrap_or(false) && !leaves_work_tree {
+        clean_worktree(&git_ctx, &project_path).map_err(|e| ProjectError::GitOperation {
+            project_name: config.project.clone(),
+            source: e,
+        })?;
+    }
+
+    if config.verify_signature.unwrap_or(false) {
+        verify_head_signature(&git_ctx, &project_path).map_err(|e| ProjectError::GitOperation {
+            project_name: config.project.clone(),
+            source: e,
+        })?;
+    }
+
+    check_expectations(config, &git_ctx, &project_path)?;
+
+    if let Some(post_update) = &config.post_update_command {
+        if is_dry_run() {
+            announce_dry_run(
+                progress_bar,
+                &format!(
+                    "{}: would run post_update_command '{}'",
+                    config.project, post_update
+                ),
+            );
+        } else {
+            run_hook(&project_path, post_update, &config.project, progress_bar)?;
+        }
+    }
+
+    let full_gc = FULL_GC.load(Ordering::Relaxed);
+    if full_gc || config.run_gc.or(global_config.run_gc).unwrap_or(false) {
+        match run_gc(&git_ctx, &project_path, full_gc) {
+            Ok(elapsed) if !is_dry_run() => log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Project '{}': git gc finished in {:.1}s.",
+                    config.project,
+                    elapsed.as_secs_f64()
+                ),
+            ),
+            Ok(_) => {}
+            Err(e) => log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Continuing after git gc error: {}",
+                    config.project, e
+                ),
+            ),
+        }
+    }
+
+    let success_msg = format!("Finished checking/updating project: {}", config.project);
+    log_to_file(LogLevel::Success, &success_msg);
+    Ok(outcome)
+}
+
+/// Updates a bare or mirror clone and records the new commits on each
+/// branch that moved. Branches that appeared are only logged.
+fn sync_bare_and_record(
+    config: &ProjectConfig,
+    git_ctx: &GitContext,
+    project_path: &Path,
+    outcome: &mut ProjectOutcome,
+) -> Result<(), ProjectError> {
+    let before = list_branch_heads(git_ctx, project_path).unwrap_or_default();
+    sync_bare_clone(git_ctx, project_path, config.mirror.unwrap_or(false)).map_err(|e| {
+        ProjectError::GitOperation {
+            project_name: config.project.clone(),
+            source: e,
+        }
+    })?;
+    if is_dry_run() {
+        return Ok(());
+    }
+    let Ok(after) = list_branch_heads(git_ctx, project_path) else {
+        return Ok(());
+    };
+
+    for (branch, new_commit) in &after {
+        match before.iter().find(|(old_branch, _)| old_branch == branch) {
+            Some((_, old_commit)) if old_commit == new_commit => {}
+            Some((_, old_commit)) => record_branch_update(
+                git_ctx,
+                project_path,
+                branch.clone(),
+                old_commit,
+                new_commit,
+                outcome,
+            ),
+            None => log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Project '{}': New branch '{}' at {}.",
+                    config.project,
+                    branch,
+                    abbreviate_hash(new_commit)
+                ),
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Pulls every branch in [`ProjectConfig::effective_pull_branches`] (or the
+/// current branch), restoring the original branch afterwards unless
+/// `leave_on_last_pulled` is set.
+/// Failures are logged and the remaining branches are still pulled.
+fn pull_configured_branches(
+    config: &ProjectConfig,
+    global_config: &GlobalConfig,
+    git_ctx: &GitContext,
+    project_path: &Path,
+    multi_progress: &MultiProgress,
+    outcome: &mut ProjectOutcome,
+) {
+    let branch_entries = match config.effective_pull_branches(global_config) {
+        None => None,
+        Some([]) => match global_config.empty_pull_branches_means.unwrap_or_default() {
+            EmptyPullBranches::Current => None,
+            EmptyPullBranches::All => Some(vec!["*".to_string()]),
+            EmptyPullBranches::Default => {
+                match get_remote_default_branch(git_ctx, project_path, git_ctx.remote) {
+                    Ok(branch) => Some(vec![branch]),
+                    Err(e) => {
+                        log_to_file(
+                        LogLevel::Warning,
+                        &format!(
+                            "Project '{}': Could not determine the default branch of '{}', pulling the current branch instead. Error: {}",
+                            config.project, git_ctx.remote, e
+                        ),
+                    );
+                        None
+                    }
+                }
+            }
+        },
+        Some(entries) => Some(entries.to_vec()),
+    };
+
+    let fetch_only = config.fetch_only.unwrap_or(false);
+    let reset_on_conflict = reset_on_conflict(config);
+    let Some(branch_entries) = branch_entries else {
+        if fetch_only {
+            log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Project '{}': fetch_only without branches, fetching all remotes.",
+                    config.project
+                ),
+            );
+        } else {
+            let current_branch_for_log = get_current_branch(git_ctx, project_path)
+                .map_or_else(|_| "current".to_string(), |state| state.to_string());
+            log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Project '{}': No branches to switch to, pulling {} branch.",
+                    config.project, current_branch_for_log
+                ),
+            );
+        }
+        if let Err(e) = pull_and_record(
+            git_ctx,
+            project_path,
+            None,
+            fetch_only,
+            reset_on_conflict,
+            outcome,
+        ) {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Continuing after pull error on current branch: {}",
+                    config.project, e
+                ),
+            );
+        }
+        return;
+    };
+
+    let branches_to_pull = expand_branch_patterns(git_ctx, project_path, &branch_entries);
+    if fetch_only {
+        // Nothing is checked out, so there is no original branch to restore.
+        for branch_name in &branches_to_pull {
+            if let Err(e) = pull_and_record(
+                git_ctx,
+                project_path,
+                Some(branch_name),
+                true,
+                false,
+                outcome,
+            ) {
+                log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "Project '{}': Continuing after fetch error on branch {}: {}",
+                        config.project, branch_name, e
+                    ),
+                );
+            }
+        }
+        return;
+    }
+    let original_branch = match get_current_branch(git_ctx, project_path) {
+        Ok(branch) => {
+            log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Project '{}': Current branch is '{}'.",
+                    config.project, branch
+                ),
+            );
+            Some(branch)
+        }
+        Err(e) => {
+            let err_msg = format!(
+                "Project '{}': Could not determine current branch. Error: {}. Will proceed without restoring branch.",
+                config.project, e
+            );
+            git_ctx
+                .progress_bar
+                .set_message(format!("{} - Branch check failed", config.project));
+            log_to_file(LogLevel::Warning, &err_msg);
+            None
+        }
+    };
+
+    let branch_bar = (branches_to_pull.len() > 1)
+        .then(|| add_branch_bar(multi_progress, git_ctx.progress_bar, branches_to_pull.len()));
+    let mut last_checked_out: Option<&str> = None;
+    let mut missing_branches: Vec<&str> = Vec::new();
+    for branch_name in &branches_to_pull {
+        if let Some(bar) = &branch_bar {
+            bar.set_message(branch_name.clone());
+        }
+        git_ctx.progress_bar.set_message(format!(
+            "{} - Switching to branch {}",
+            config.project, branch_name
+        ));
+        // A branch the project does not have (e.g. from --branch) is an
+        // expected skip, not a checkout failure.
+        let exists = |reference: String| {
+            resolve_commit(git_ctx, project_path, &reference)
+                .ok()
+                .flatten()
+                .is_some()
+        };
+        let branch_exists = exists(format!("refs/heads/{}", branch_name))
+            || exists(format!("refs/remotes/{}/{}", git_ctx.remote, branch_name));
+        let checkout_result = if branch_exists {
+            checkout_branch(git_ctx, project_path, branch_name).map(Some)
+        } else {
+            Ok(None)
+        };
+        match checkout_result {
+            Ok(None) => {
+                log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "Project '{}': Branch '{}' does not exist locally or on {}. Skipping it.",
+                        config.project, branch_name, git_ctx.remote
+                    ),
+                );
+                missing_branches.push(branch_name);
+            }
+            Ok(Some(())) => {
+                last_checked_out = Some(branch_name);
+                if let Err(e) = pull_and_record(
+                    git_ctx,
+                    project_path,
+                    Some(branch_name),
+                    false,
+                    reset_on_conflict,
+                    outcome,
+                ) {
+                    log_to_file(
+                        LogLevel::Warning,
+                        &format!(
+                            "Project '{}': Continuing after pull error on branch {}: {}",
+                            config.project, branch_name, e
+                        ),
+                    );
+                }
+            }
+            Err(e) => {
+                let err_msg = format!(
+                    "Project '{}': Failed to checkout branch '{}'. Skipping pull for this branch. Error: {}",
+                    config.project, branch_name, e
+                );
+                git_ctx.progress_bar.set_message(format!(
+                    "{} - Checkout failed: {}",
+                    config.project, branch_name
+                ));
+                log_to_file(LogLevel::Error, &err_msg);
+            }
+        }
+        if let Some(bar) = &branch_bar {
+            bar.inc(1);
+        }
+    }
+    if let Some(bar) = branch_bar {
+        bar.finish_and_clear();
+        multi_progress.remove(&bar);
+    }
+    if !missing_branches.is_empty() && missing_branches.len() == branches_to_pull.len() {
+        outcome.skipped = Some(format!(
+            "Project '{}': None of the branches to pull exist ({}).",
+            config.project,
+            missing_branches.join(", ")
+        ));
+    }
+
+    if let (true, Some(last_branch)) = (
+        config.leave_on_last_pulled.unwrap_or(false),
+        last_checked_out,
+    ) {
+        log_to_file(
+            LogLevel::Info,
+            &format!(
+                "Project '{}': leave_on_last_pulled is set, staying on '{}' instead of restoring the original branch.",
+                config.project, last_branch
+            ),
+        );
+    } else if let Some(orig_branch_name) = original_branch {
+        // Check if current branch is different from original, or if original wasn't in pull_branches list
+        let current_branch_after_pulls = get_current_branch(git_ctx, project_path).ok();
+        if current_branch_after_pulls.as_ref() != Some(&orig_branch_name) {
+            log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Project '{}': Attempting to restore original branch '{}'.",
+                    config.project, orig_branch_name
+                ),
+            );
+            git_ctx.progress_bar.set_message(format!(
+                "{} - Restoring original branch {}",
+                config.project, orig_branch_name
+            ));
+            if let Err(e) = restore_branch_state(git_ctx, project_path, &orig_branch_name) {
+                let err_msg = format!(
+                    "Project '{}': Failed to restore original branch '{}'. Error: {}",
+                    config.project, orig_branch_name, e
+                );
+                git_ctx.progress_bar.set_message(format!(
+                    "{} - Restore failed: {}",
+                    config.project, orig_branch_name
+                ));
+                log_to_file(LogLevel::Warning, &err_msg);
+            }
+        } else {
+            log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Project '{}': Already on original branch '{}' or no restoration needed.",
+                    config.project, orig_branch_name
+                ),
+            );
+        }
+    }
+}
+
+/// Whether a conflicted pull is reset hard, from `--reset-on-conflict` or
+/// the project's `reset_on_conflict`.
+fn reset_on_conflict(config: &ProjectConfig) -> bool {
+    RESET_ON_CONFLICT.load(Ordering::Relaxed) || config.reset_on_conflict.unwrap_or(false)
+}
+
+/// Adds a bar counting pulled branches right below the project's spinner.
+fn add_branch_bar(
+    multi_progress: &MultiProgress,
+    project_bar: &ProgressBar,
+    branches: usize,
+) -> ProgressBar {
+    let bar = multi_progress.insert_after(project_bar, ProgressBar::new(branches as u64));
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("      [{bar:20.cyan/blue}] {pos}/{len} branches {wide_msg}")
+            .expect("Failed to set progress bar template"),
+    );
+    bar
+}
+
+/// Fails the project when `HEAD` is not on `expect_branch` or not at
+/// `expect_commit`. Commit hashes match by prefix, ignoring case.
+fn check_expectations(
+    config: &ProjectConfig,
+    git_ctx: &GitContext,
+    project_path: &Path,
+) -> Result<(), ProjectError> {
+    let git_error = |e: GitError| ProjectError::GitOperation {
+        project_name: config.project.clone(),
+        source: e,
+    };
+    let mismatch = |message: String| ProjectError::ExpectationFailed {
+        project_name: config.project.clone(),
+        message,
+    };
+
+    if let Some(expected) = &config.expect_branch {
+        match get_current_branch(git_ctx, project_path).map_err(git_error)? {
+            BranchState::Detached(_) => {
+                return Err(mismatch(format!(
+                    "expected branch '{}', but HEAD is detached",
+                    expected
+                )));
+            }
+            BranchState::Named(actual) if actual != *expected => {
+                return Err(mismatch(format!(
+                    "expected branch '{}', found '{}'",
+                    expected, actual
+                )));
+            }
+            BranchState::Named(_) => {}
+        }
+    }
+    if let Some(expected) = &config.expect_commit {
+        let actual = get_head_commit(git_ctx, project_path).map_err(git_error)?;
+        if !actual
+            .to_ascii_lowercase()
+            .starts_with(&expected.to_ascii_lowercase())
+        {
+            return Err(mismatch(format!(
+                "expected commit '{}', found '{}'",
+                expected,
+                abbreviate_hash(&actual)
+            )));
+        }
+    }
+    if config.expect_branch.is_some() || config.expect_commit.is_some() {
+        log_to_file(
+            LogLevel::Info,
+            &format!(
+                "Project '{}': HEAD matches expect_branch/expect_commit.",
+                config.project
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// Compares the clone at `project_path` with what `config` declares: that it
+/// exists, its remote is `url`, and `HEAD` matches `checkout_tag`,
+/// `expect_branch` and `expect_commit`. Read-only; returns one description
+/// per mismatch.
+pub fn detect_drift(config: &ProjectConfig, project_path: &Path) -> Vec<String> {
+    if !is_git_repo(project_path) {
+        return vec![format!("no clone at '{}'", project_path.display())];
+    }
+    let progress_bar = ProgressBar::hidden();
+    let git_ctx = GitContext {
+        project_name: &config.project,
+        progress_bar: &progress_bar,
+        success_exit_codes: &[],
+        ssh_key: None,
+        remote: config.remote_name(),
+    };
+    let mut drift = Vec::new();
+
+    match get_remote_url(&git_ctx, project_path, git_ctx.remote) {
+        Ok(url) if same_remote(&url, &config.url) => {}
+        Ok(url) => drift.push(format!(
+            "{} is '{}', config says '{}'",
+            git_ctx.remote,
+            redact_url(&url),
+            redact_url(&config.url)
+        )),
+        Err(_) => drift.push(format!("has no '{}' remote", git_ctx.remote)),
+    }
+
+    let head = get_head_commit(&git_ctx, project_path).ok();
+    if let Some(tag) = &config.checkout_tag {
+        match get_tag_commit(&git_ctx, project_path, tag) {
+            Ok(Some(tag_commit)) if head.as_deref() == Some(tag_commit.as_str()) => {}
+            Ok(Some(_)) => drift.push(format!("HEAD is not at tag '{}'", tag)),
+            _ => drift.push(format!("tag '{}' does not exist locally", tag)),
+        }
+    }
+    if let Some(expected) = &config.expect_branch {
+        match get_current_branch(&git_ctx, project_path) {
+            Ok(BranchState::Named(actual)) if actual == *expected => {}
+            Ok(BranchState::Detached(_)) => drift.push(format!(
+                "expected branch '{}', but HEAD is detached",
+                expected
+            )),
+            Ok(BranchState::Named(actual)) => drift.push(format!(
+                "expected branch '{}', found '{}'",
+                expected, actual
+            )),
+            Err(_) => drift.push(format!(
+                "expected branch '{}', but the branch could not be read",
+                expected
+            )),
+        }
+    }
+    if let Some(expected) = &config.expect_commit {
+        match &head {
+            Some(actual)
+                if actual
+                    .to_ascii_lowercase()
+                    .starts_with(&expected.to_ascii_lowercase()) => {}
+            Some(actual) => drift.push(format!(
+                "expected commit '{}', found '{}'",
+                expected,
+                abbreviate_hash(actual)
+            )),
+            None => drift.push(format!(
+                "expected commit '{}', but HEAD could not be read",
+                expected
+            )),
+        }
+    }
+    drift
+}
+
+/// What `--verify` found in one clone: a cell per column of its health table
+/// and every check that failed.
+pub struct ProjectHealth {
+    pub repository: &'static str,
+    pub branch: String,
+    pub work_tree: &'static str,
+    /// `ok`, `failed`, or `-` when `git fsck` was not run.
+    pub fsck: &'static str,
+    pub problems: Vec<String>,
+}
+
+/// `--verify`: inspects the clone at `project_path` without changing it.
+/// Everything [`detect_drift`] compares is checked, plus local changes and,
+/// with `fsck`, the object database.
+pub fn verify_project(config: &ProjectConfig, project_path: &Path, fsck: bool) -> ProjectHealth {
+    let mut health = ProjectHealth {
+        repository: "ok",
+        branch: "-".to_string(),
+        work_tree: "-",
+        fsck: "-",
+        problems: detect_drift(config, project_path),
+    };
+    if !is_git_repo(project_path) {
+        health.repository = if project_path.exists() {
+            "not a repository"
+        } else {
+            "missing"
+        };
+        return health;
+    }
+    let progress_bar = ProgressBar::hidden();
+    let git_ctx = GitContext {
+        project_name: &config.project,
+        progress_bar: &progress_bar,
+        success_exit_codes: &[],
+        ssh_key: None,
+        remote: config.remote_name(),
+    };
+
+    if config.is_bare() {
+        health.branch = "(bare)".to_string();
+    } else {
+        health.branch = get_current_branch(&git_ctx, project_path)
+            .map_or_else(|_| "unknown".to_string(), |state| state.to_string());
+        match has_uncommitted_changes(&git_ctx, project_path) {
+            Ok(false) => health.work_tree = "clean",
+            Ok(true) => {
+                health.work_tree = "dirty";
+                health.problems.push("has uncommitted changes".to_string());
+            }
+            Err(e) => {
+                health.work_tree = "unknown";
+                health.problems.push(format!(
+                    "status could not be read: {}",
+                    git_error_summary(&e)
+                ));
+            }
+        }
+    }
+    if fsck {
+        match check_integrity(&git_ctx, project_path) {
+            Ok(()) => health.fsck = "ok",
+            Err(e) => {
+                health.fsck = "failed";
+                health
+                    .problems
+                    .push(format!("git fsck failed: {}", git_error_summary(&e)));
+            }
+        }
+    }
+    health
+}
+
+/// Checks a processed project for local changes, a detached `HEAD` or
+/// divergence from upstream. Returns `None` when there is no repository to
+/// inspect or git could not be run.
+pub fn collect_post_status(
+    config: &ProjectConfig,
+    global_config: &GlobalConfig,
+    project_path: &Path,
+    progress_bar: &ProgressBar,
+) -> Option<PostStatus> {
+    // Bare clones have no work tree to be dirty, detached or diverged.
+    if !is_git_repo(project_path) || config.is_bare() {
+        return None;
+    }
+    let ssh_key = config.ssh_key_path();
+    let git_ctx = GitContext {
+        project_name: &config.project,
+        progress_bar,
+        success_exit_codes: config
+            .success_exit_codes
+            .as_deref()
+            .or(global_config.success_exit_codes.as_deref())
+            .unwrap_or_default(),
+        ssh_key: ssh_key.as_deref(),
+        remote: config.remote_name(),
+    };
+
+    let status = has_uncommitted_changes(&git_ctx, project_path).and_then(|dirty| {
+        let detached = matches!(
+            get_current_branch(&git_ctx, project_path)?,
+            BranchState::Detached(_)
+        );
+        let (ahead, behind) = get_ahead_behind(&git_ctx, project_path)?.unwrap_or_default();
+        Ok(PostStatus {
+            dirty,
+            detached,
+            ahead,
+            behind,
+        })
+    });
+    match status {
+        Ok(status) => Some(status),
+        Err(e) => {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not collect post-run status: {}",
+                    config.project, e
+                ),
+            );
+            None
+        }
+    }
+}
+
+#[cfg(feature = "archive")]
+fn import_archive(
+    config: &ProjectConfig,
+    project_path: &Path,
+    progress_bar: &ProgressBar,
+) -> Result<(), ProjectError> {
+    let archive_url = config
+        .archive_url
+        .clone()
+        .or_else(|| default_archive_url(&config.url))
+        .ok_or_else(|| ProjectError::Archive {
+            project_name: config.project.clone(),
+            message: "no archive_url configured and none can be derived from url".to_string(),
+        })?;
+    import_from_archive(&archive_url, project_path, &config.project, progress_bar)
+}
+
+#[cfg(not(feature = "archive"))]
+fn import_archive(
+    config: &ProjectConfig,
+    _project_path: &Path,
+    _progress_bar: &ProgressBar,
+) -> Result<(), ProjectError> {
+    Err(ProjectError::Archive {
+        project_name: config.project.clone(),
+        message: "archive_fallback requires a build with the 'archive' feature".to_string(),
+    })
+}
+
+fn is_branch_pattern(entry: &str) -> bool {
+    entry.contains(['*', '?', '['])
+}
+
+/// Expands glob entries in `pull_branches` (e.g. `release/*`) against the
+/// remote's branches. Literal entries pass through unchanged; order is kept
+/// and duplicates are dropped.
+fn expand_branch_patterns(
+    git_ctx: &GitContext,
+    project_path: &Path,
+    entries: &[String],
+) -> Vec<String> {
+    if !entries.iter().any(|entry| is_branch_pattern(entry)) {
+        return entries.to_vec();
+    }
+
+    let remote_branches = match list_remote_branches(git_ctx, project_path, git_ctx.remote) {
+        Ok(branches) => branches,
+        Err(e) => {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not list remote branches to expand patterns, skipping them. Error: {}",
+                    git_ctx.project_name, e
+                ),
+            );
+            Vec::new()
+        }
+    };
+
+    let mut expanded: Vec<String> = Vec::new();
+    let mut matched_any = false;
+    for entry in entries {
+        if !is_branch_pattern(entry) {
+            if !expanded.contains(entry) {
+                expanded.push(entry.clone());
+            }
+            continue;
+        }
+
+        let matcher = match Glob::new(entry) {
+            Ok(glob) => glob.compile_matcher(),
+            Err(e) => {
+                log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "Project '{}': Invalid branch pattern '{}' in pull_branches: {}",
+                        git_ctx.project_name, entry, e
+                    ),
+                );
+                continue;
+            }
+        };
+        let matches: Vec<&String> = remote_branches
+            .iter()
+            .filter(|branch| matcher.is_match(branch.as_str()))
+            .collect();
+        log_to_file(
+            LogLevel::Info,
+            &format!(
+                "Project '{}': Branch pattern '{}' matched {} branch(es){}{}",
+                git_ctx.project_name,
+                entry,
+                matches.len(),
+                if matches.is_empty() { "." } else { ": " },
+                matches
+                    .iter()
+                    .map(|b| b.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        );
+        for branch in matches {
+            matched_any = true;
+            if !expanded.contains(branch) {
+                expanded.push(branch.clone());
+            }
+        }
+    }
+
+    // Matched branches may be new upstream; fetch so they can be checked out.
+    if matched_any {
+        if let Err(e) = fetch_remote(git_ctx, project_path, git_ctx.remote) {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Fetch before checking out matched branches failed: {}",
+                    git_ctx.project_name, e
+                ),
+            );
+        }
+    }
+    expanded
+}
+
+/// Pulls `branch` (or the current branch) and records any commits it brought
+/// in. With `fetch_only` the branch is only fetched, and the commits are those
+/// that moved its remote-tracking ref.
+fn pull_and_record(
+    git_ctx: &GitContext,
+    project_path: &Path,
+    branch: Option<&str>,
+    fetch_only: bool,
+    reset_on_conflict: bool,
+    outcome: &mut ProjectOutcome,
+) -> Result<(), GitError> {
+    let tracked_ref = match (fetch_only, branch) {
+        (true, Some(branch)) => Some(format!("refs/remotes/{}/{}", git_ctx.remote, branch)),
+        // `git fetch --all` updates refs that are not tracked individually.
+        (true, None) => None,
+        (false, _) => Some("HEAD".to_string()),
+    };
+    let resolve = |reference: &Option<String>| {
+        reference.as_deref().and_then(|reference| {
+            resolve_commit(git_ctx, project_path, reference)
+                .ok()
+                .flatten()
+        })
+    };
+    let before = resolve(&tracked_ref);
+    let options = PullOptions {
+        remote: git_ctx.remote,
+        branch,
+        fetch_only,
+    };
+    match pull_branch_updates(git_ctx, project_path, &options) {
+        Err(e) if reset_on_conflict && is_merge_conflict(&e) => {
+            log_to_file(LogLevel::Error, &e.to_string());
+            reset_hard_to_remote(git_ctx, project_path, branch)?;
+        }
+        result => result?,
+    }
+
+    let (Some(before), Some(after)) = (before, resolve(&tracked_ref)) else {
+        return Ok(());
+    };
+    if before == after {
+        return Ok(());
+    }
+
+    let branch_name = match branch {
+        Some(branch) if fetch_only => format!("{}/{}", git_ctx.remote, branch),
+        Some(branch) => branch.to_string(),
+        None => get_current_branch(git_ctx, project_path)
+            .map_or_else(|_| "current branch".to_string(), |state| state.to_string()),
+    };
+    record_branch_update(git_ctx, project_path, branch_name, &before, &after, outcome);
+    Ok(())
+}
+
+/// Logs how far `branch` moved, as `3 new commits, 12 files changed, ...`,
+/// and adds the new commits and diffstat to `outcome`.
+fn record_branch_update(
+    git_ctx: &GitContext,
+    project_path: &Path,
+    branch: String,
+    before: &str,
+    after: &str,
+    outcome: &mut ProjectOutcome,
+) {
+    let commits = match list_commits_between(git_ctx, project_path, before, after) {
+        Ok(commits) => commits,
+        Err(e) => {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not list new commits on {}: {}",
+                    git_ctx.project_name, branch, e
+                ),
+            );
+            return;
+        }
+    };
+    let diffstat = match diff_shortstat(git_ctx, project_path, before, after) {
+        Ok(stat) => Some(stat).filter(|stat| !stat.is_empty()),
+        Err(e) => {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not compute the diffstat of {}: {}",
+                    git_ctx.project_name, branch, e
+                ),
+            );
+            None
+        }
+    };
+    let mut summary = format!(
+        "{} new commit{}",
+        commits.len(),
+        if commits.len() == 1 { "" } else { "s" }
+    );
+    if let Some(stat) = &diffstat {
+        summary.push_str(", ");
+        summary.push_str(stat);
+    }
+    log_to_file(
+        LogLevel::Info,
+        &format!(
+            "Project '{}': {} moved {} -> {}: {}.",
+            git_ctx.project_name,
+            branch,
+            abbreviate_hash(before),
+            abbreviate_hash(after),
+            summary
+        ),
+    );
+    outcome.branch_updates.push(BranchUpdate {
+        branch,
+        commits,
+        diffstat,
+    });
+}
+
+fn diff_submodule_pins(
+    before: &[(String, String)],
+    after: &[(String, String)],
+) -> Vec<SubmoduleChange> {
+    let find = |pins: &[(String, String)], path: &str| {
+        pins.iter()
+            .find(|(pin_path, _)| pin_path == path)
+            .map(|(_, sha)| sha.clone())
+    };
+    let mut changes: Vec<SubmoduleChange> = after
+        .iter()
+        .filter_map(|(path, new_sha)| {
+            let old = find(before, path);
+            (old.as_ref() != Some(new_sha)).then(|| SubmoduleChange {
+                path: path.clone(),
+                old: old.as_deref().map(abbreviate_hash),
+                new: Some(abbreviate_hash(new_sha)),
+            })
+        })
+        .collect();
+    changes.extend(
+        before
+            .iter()
+            .filter(|(path, _)| find(after, path).is_none())
+            .map(|(path, old_sha)| SubmoduleChange {
+                path: path.clone(),
+                old: Some(abbreviate_hash(old_sha)),
+                new: None,
+            }),
+    );
+    changes
+}
+
+/// Sets the clone's remote to the configured `url`, e.g. after an
+/// organization was renamed. URLs differing only in a trailing `.git` or `/`
+/// are left alone. Failures are logged and the update goes on with the old
+/// URL.
+fn update_remote_url(config: &ProjectConfig, git_ctx: &GitContext, project_path: &Path) {
+    let current_url = match get_remote_url(git_ctx, project_path, git_ctx.remote) {
+        Ok(url) if same_remote(&url, &config.url) => return,
+        Ok(url) => url,
+        Err(e) => {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not read the remote URL to update it. Error: {}",
+                    config.project, e
+                ),
+            );
+            return;
+        }
+    };
+    git_ctx.progress_bar.set_message(format!(
+        "{} - Updating {} URL",
+        config.project, git_ctx.remote
+    ));
+    match set_remote_url(git_ctx, project_path, &config.url) {
+        Ok(()) if is_dry_run() => {}
+        Ok(()) => log_to_file(
+            LogLevel::Success,
+            &format!(
+                "Project '{}': Changed {} from '{}' to '{}' (--update-remotes).",
+                config.project,
+                git_ctx.remote,
+                redact_url(&current_url),
+                redact_url(&config.url)
+            ),
+        ),
+        Err(e) => log_to_file(
+            LogLevel::Warning,
+            &format!(
+                "Project '{}': Could not change {} to '{}'. Error: {}",
+                config.project,
+                git_ctx.remote,
+                redact_url(&config.url),
+                e
+            ),
+        ),
+    }
+}
+
+/// Moves an existing clone aside when the configured URL now points at a
+/// repository that shares no history with it, so the caller re-clones fresh.
+fn backup_if_remote_replaced(
+    config: &ProjectConfig,
+    git_ctx: &GitContext,
+    project_path: &Path,
+) -> Result<(), ProjectError> {
+    let current_url = match get_remote_url(git_ctx, project_path, git_ctx.remote) {
+        Ok(url) => url,
+        Err(e) => {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not read the remote URL, skipping re-clone check. Error: {}",
+                    config.project, e
+                ),
+            );
+            return Ok(());
+        }
+    };
+    if same_remote(&current_url, &config.url) {
+        return Ok(());
+    }
+
+    git_ctx.progress_bar.set_message(format!(
+        "{} - Comparing history with new URL",
+        config.project
+    ));
+    match remote_shares_history(git_ctx, project_path, &config.url) {
+        Ok(true) => {
+            log_to_file(
+                LogLevel::Info,
+                &format!(
+                    "Project '{}': Origin URL differs from config but shares history; keeping existing clone.",
+                    config.project
+                ),
+            );
+            Ok(())
+        }
+        Ok(false) => {
+            let backup_path = PathBuf::from(format!(
+                "{}.backup-{}",
+                project_path.display(),
+                chrono::Local::now().format("%Y%m%d%H%M%S")
+            ));
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': '{}' is an unrelated repository to the existing clone of '{}'. Moving it to '{}' and re-cloning.",
+                    config.project,
+                    redact_url(&config.url),
+                    redact_url(&current_url),
+                    backup_path.display()
+                ),
+            );
+            fs::rename(project_path, &backup_path).map_err(|e| ProjectError::Backup {
+                project_name: config.project.clone(),
+                path: project_path.to_path_buf(),
+                source: e,
+            })
+        }
+        Err(e) => {
+            log_to_file(
+                LogLevel::Warning,
+                &format!(
+                    "Project '{}': Could not compare history with '{}', keeping existing clone. Error: {}",
+                    config.project,
+                    redact_url(&config.url),
+                    e
+                ),
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(name: &str, path: &str) -> ProjectConfig {
+        serde_json::from_value(serde_json::json!({
+            "project": name,
+            "url": format!("https://example.com/{}.git", name),
+            "path": path,
+        }))
+        .expect("minimal project config")
+    }
+
+    fn rejected(result: &Result<PathBuf, ProjectError>) -> Option<&str> {
+        match result {
+            Err(ProjectError::UnsafePath { reason, .. }) => Some(reason),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn rejects_path_equal_to_parent_dir() {
+        let projects = [
+            project("dot", "."),
+            project("empty", ""),
+            project("back", "sub/.."),
+        ];
+        let results = preflight_project_paths(&projects, Path::new("/work/src"));
+        for result in &results {
+            assert_eq!(
+                rejected(result),
+                Some("it is the clone parent directory itself")
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_ancestor_listed_before_descendant() {
+        let projects = [project("outer", "apps"), project("inner", "apps/web")];
+        let results = preflight_project_paths(&projects, Path::new("/work/src"));
+        assert_eq!(
+            rejected(&results[0]),
+            Some("it contains the path of project 'inner'")
+        );
+        assert_eq!(
+            results[1].as_ref().ok(),
+            Some(&PathBuf::from("/work/src/apps/web"))
+        );
+    }
+
+    #[test]
+    fn rejects_ancestor_listed_after_descendant() {
+        let projects = [
+            project("inner", "apps/web"),
+            project("outer", "/work/src/apps"),
+        ];
+        let results = preflight_project_paths(&projects, Path::new("/work/src"));
+        assert_eq!(
+            results[0].as_ref().ok(),
+            Some(&PathBuf::from("/work/src/apps/web"))
+        );
+        assert_eq!(
+            rejected(&results[1]),
+            Some("it contains the path of project 'inner'")
+        );
+    }
+
+    #[test]
+    fn accepts_non_overlapping_siblings() {
+        // `apps/web` is a string prefix of `apps/webhooks`, but not a path ancestor.
+        let projects = [
+            project("web", "apps/web"),
+            project("hooks", "apps/webhooks"),
+            project("api", "./api"),
+        ];
+        let results = preflight_project_paths(&projects, Path::new("/work/src"));
+        let paths: Vec<PathBuf> = results
+            .into_iter()
+            .map(|r| r.expect("no overlap"))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("/work/src/apps/web"),
+                PathBuf::from("/work/src/apps/webhooks"),
+                PathBuf::from("/work/src/api"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use crate::config::ProjectConfig;
+use crate::git_utils::CommitSummary;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectStatus {
+    Cloned,
+    Updated,
+    #[default]
+    UpToDate,
+    Skipped,
+    Error,
+}
+
+/// What happened to a single project during a run.
+#[derive(Serialize, Debug, Default)]
+pub struct ProjectOutcome {
+    pub project: String,
+    pub status: ProjectStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u128,
+    #[serde(skip)]
+    pub cloned: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub branch_updates: Vec<BranchUpdate>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub submodule_changes: Vec<SubmoduleChange>,
+    /// Why the update was skipped, if it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    /// Repository state after the update; only collected with `--post-status`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_status: Option<PostStatus>,
+}
+
+/// Leftover state that may need manual follow-up after an update.
+#[derive(Serialize, Debug, Default)]
+pub struct PostStatus {
+    pub dirty: bool,
+    pub detached: bool,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+impl PostStatus {
+    pub fn needs_attention(&self) -> bool {
+        self.dirty || self.detached || self.ahead > 0 || self.behind > 0
+    }
+
+    pub fn describe(&self) -> String {
+        let mut problems = Vec::new();
+        if self.dirty {
+            problems.push("uncommitted changes".to_string());
+        }
+        if self.detached {
+            problems.push("detached HEAD".to_string());
+        }
+        match (self.ahead, self.behind) {
+            (0, 0) => {}
+            (ahead, 0) => problems.push(format!("{} commit(s) ahead of upstream", ahead)),
+            (0, behind) => problems.push(format!("{} commit(s) behind upstream", behind)),
+            (ahead, behind) => problems.push(format!(
+                "diverged from upstream ({} ahead, {} behind)",
+                ahead, behind
+            )),
+        }
+        problems.join(", ")
+    }
+}
+
+/// New commits that arrived on one branch during a pull.
+#[derive(Serialize, Debug)]
+pub struct BranchUpdate {
+    pub branch: String,
+    pub commits: Vec<CommitSummary>,
+    /// `git diff --shortstat` of the update, e.g. `12 files changed, 40
+    /// insertions(+), 3 deletions(-)`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diffstat: Option<String>,
+}
+
+/// A submodule whose pinned commit changed during the update. `old` is absent
+/// for newly added submodules and `new` for removed ones.
+#[derive(Serialize, Debug)]
+pub struct SubmoduleChange {
+    pub path: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl ProjectOutcome {
+    pub fn new(project: &str) -> Self {
+        ProjectOutcome {
+            project: project.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn failed(project: &str, error: String, duration: Duration) -> Self {
+        ProjectOutcome {
+            project: project.to_string(),
+            status: ProjectStatus::Error,
+            error: Some(error),
+            duration_ms: duration.as_millis(),
+            ..Default::default()
+        }
+    }
+
+    /// Derives the final status from what was recorded and stamps the duration.
+    pub fn finish(mut self, duration: Duration) -> Self {
+        self.status = if self.skipped.is_some() {
+            ProjectStatus::Skipped
+        } else if self.cloned {
+            ProjectStatus::Cloned
+        } else if self.has_new_commits() {
+            ProjectStatus::Updated
+        } else {
+            ProjectStatus::UpToDate
+        };
+        self.duration_ms = duration.as_millis();
+        self
+    }
+
+    pub fn has_new_commits(&self) -> bool {
+        self.branch_updates
+            .iter()
+            .any(|update| !update.commits.is_empty())
+    }
+}
+
+/// Whole-run summary: every project's outcome plus totals.
+#[derive(Serialize, Debug)]
+pub struct RunReport<'a> {
+    pub total_duration_ms: u128,
+    pub counts: StatusCounts,
+    pub projects: &'a [ProjectOutcome],
+}
+
+/// Number of projects that ended in each status.
+#[derive(Serialize, Debug, Default)]
+pub struct StatusCounts {
+    pub total: usize,
+    pub cloned: usize,
+    pub updated: usize,
+    pub up_to_date: usize,
+    pub skipped: usize,
+    pub error: usize,
+}
+
+impl<'a> RunReport<'a> {
+    pub fn new(outcomes: &'a [ProjectOutcome], total_duration: Duration) -> Self {
+        let mut counts = StatusCounts {
+            total: outcomes.len(),
+            ..Default::default()
+        };
+        for outcome in outcomes {
+            match outcome.status {
+                ProjectStatus::Cloned => counts.cloned += 1,
+                ProjectStatus::Updated => counts.updated += 1,
+                ProjectStatus::UpToDate => counts.up_to_date += 1,
+                ProjectStatus::Skipped => counts.skipped += 1,
+                ProjectStatus::Error => counts.error += 1,
+            }
+        }
+        RunReport {
+            total_duration_ms: total_duration.as_millis(),
+            counts,
+            projects: outcomes,
+        }
+    }
+
+    /// Writes the report to `path` as pretty-printed JSON, replacing any
+    /// previous file.
+    pub fn write_json(&self, path: &Path) -> io::Result<()> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        fs::write(path, json)
+    }
+}
+
+/// Appends one JSON object per finished project, flushing after every line so
+/// a crash mid-run keeps everything reported so far.
+pub struct NdjsonReportWriter {
+    file: File,
+}
+
+impl NdjsonReportWriter {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(NdjsonReportWriter { file })
+    }
+
+    pub fn write(&mut self, outcome: &ProjectOutcome) -> io::Result<()> {
+        let line = serde_json::to_string(outcome)?;
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()
+    }
+}
+
+/// Writes a Markdown changelog grouping the new commits of each updated project.
+pub fn write_changelog_markdown(path: &Path, outcomes: &[ProjectOutcome]) -> io::Result<()> {
+    let mut markdown = format!(
+        "# Project changes ({})\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+
+    let updated: Vec<&ProjectOutcome> = outcomes.iter().filter(|o| o.has_new_commits()).collect();
+    if !updated.is_empty() {
+        markdown.push_str("\n## Updated\n");
+        for outcome in updated {
+            markdown.push_str(&format!("\n### {}\n", outcome.project));
+            for update in outcome
+                .branch_updates
+                .iter()
+                .filter(|u| !u.commits.is_empty())
+            {
+                let diffstat = update
+                    .diffstat
+                    .as_deref()
+                    .map(|stat| format!("; {}", stat))
+                    .unwrap_or_default();
+                markdown.push_str(&format!(
+                    "\n**{}** ({} new commit{}{})\n\n",
+                    update.branch,
+                    update.commits.len(),
+                    if update.commits.len() == 1 { "" } else { "s" },
+                    diffstat
+                ));
+                for commit in &update.commits {
+                    markdown.push_str(&format!("- `{}` {}\n", commit.hash, commit.subject));
+                }
+            }
+        }
+    }
+
+    let cloned: Vec<&str> = outcomes
+        .iter()
+        .filter(|o| o.cloned)
+        .map(|o| o.project.as_str())
+        .collect();
+    push_name_section(&mut markdown, "Newly cloned", &cloned);
+
+    let unchanged: Vec<&str> = outcomes
+        .iter()
+        .filter(|o| o.status != ProjectStatus::Error && !o.cloned && !o.has_new_commits())
+        .map(|o| o.project.as_str())
+        .collect();
+    push_name_section(&mut markdown, "No changes", &unchanged);
+
+    let failed: Vec<&str> = outcomes
+        .iter()
+        .filter(|o| o.status == ProjectStatus::Error)
+        .map(|o| o.project.as_str())
+        .collect();
+    push_name_section(&mut markdown, "Failed", &failed);
+
+    fs::write(path, markdown)
+}
+
+fn push_name_section(markdown: &mut String, title: &str, names: &[&str]) {
+    if names.is_empty() {
+        return;
+    }
+    markdown.push_str(&format!("\n## {}\n\n", title));
+    for name in names {
+        markdown.push_str(&format!("- {}\n", name));
+    }
+}
+
+/// Writes the `depends_on` graph as Graphviz DOT. Edges point from a
+/// dependency to the project waiting on it, and projects sharing an
+/// `extends` template are drawn inside a cluster named after it.
+pub fn write_dependency_dot(path: &Path, projects: &[ProjectConfig]) -> io::Result<()> {
+    let mut dot = String::from("digraph projects {\n    rankdir=LR;\n    node [shape=box];\n");
+
+    let mut templates: Vec<&str> = projects
+        .iter()
+        .filter_map(|p| p.extends.as_deref())
+        .collect();
+    templates.sort_unstable();
+    templates.dedup();
+    for (index, template) in templates.iter().enumerate() {
+        dot.push_str(&format!(
+            "\n    subgraph cluster_{} {{\n        label={};\n",
+            index,
+            dot_quote(template)
+        ));
+        for project in projects
+            .iter()
+            .filter(|p| p.extends.as_deref() == Some(template))
+        {
+            dot.push_str(&format!("        {};\n", dot_quote(&project.project)));
+        }
+        dot.push_str("    }\n");
+    }
+
+    let standalone: Vec<&ProjectConfig> = projects.iter().filter(|p| p.extends.is_none()).collect();
+    if !standalone.is_empty() {
+        dot.push('\n');
+        for project in standalone {
+            dot.push_str(&format!("    {};\n", dot_quote(&project.project)));
+        }
+    }
+
+    let edges: Vec<(&str, &str)> = projects
+        .iter()
+        .flat_map(|p| {
+            p.depends_on
+                .iter()
+                .flatten()
+                .map(move |dependency| (dependency.as_str(), p.project.as_str()))
+        })
+        .collect();
+    if !edges.is_empty() {
+        dot.push('\n');
+        for (from, to) in edges {
+            dot.push_str(&format!("    {} -> {};\n", dot_quote(from), dot_quote(to)));
+        }
+    }
+
+    dot.push_str("}\n");
+    fs::write(path, dot)
+}
+
+fn dot_quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,447 @@
+use crate::config::{url_host_and_repo_path, GlobalConfig, ProjectConfig};
+use crate::display::{print_level_status, print_status};
+use crate::error::ProjectError;
+use crate::logger::{enter_project_log, log_to_file, LogLevel};
+use crate::project_logic::{collect_post_status, process_project};
+use crate::report::{NdjsonReportWriter, ProjectOutcome, ProjectStatus};
+use crate::scheduler::{wait_for_load_below, HostSlots};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SPINNER_TICK: Duration = Duration::from_millis(120);
+
+/// Set by the first Ctrl+C: no further projects are started.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Records an interrupt. Returns whether one had already been requested.
+pub fn request_interrupt() -> bool {
+    INTERRUPTED.swap(true, Ordering::SeqCst)
+}
+
+/// Whether Ctrl+C was pressed during the run.
+pub fn was_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Worker count used when `--jobs` is not given.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |jobs| jobs.get())
+}
+
+/// What workers report back to the coordinating thread.
+enum WorkerEvent {
+    /// The project is done with its remote and gives up its host slot.
+    HostReleased(usize),
+    Finished(usize, ProjectOutcome),
+}
+
+/// Run-wide settings shared by every project.
+pub struct RunSettings<'a> {
+    pub global_config: &'a GlobalConfig,
+    pub parent_clone_dir: &'a Path,
+    pub jobs: usize,
+    pub max_load: Option<f64>,
+    pub post_status: bool,
+    pub plain_progress: bool,
+    /// `--quiet`: only failures are printed.
+    pub quiet: bool,
+    /// `--fail-fast`: start no further projects once one has failed.
+    pub fail_fast: bool,
+}
+
+/// Processes `projects` with up to `settings.jobs` running at once, each on
+/// its own thread with its own spinner in `multi_progress`. A project starts
+/// only after everything in its `depends_on` has finished, and is skipped if
+/// one of those failed; independent projects later in the config start in
+/// the meantime. With `fail_fast`, the first failure stops new
+/// projects from starting and the rest are skipped. Outcomes are returned in
+/// config order.
+pub fn run_projects(
+    projects: &[ProjectConfig],
+    path_checks: Vec<Result<PathBuf, ProjectError>>,
+    settings: &RunSettings,
+    multi_progress: &MultiProgress,
+    overall_progress_bar: &ProgressBar,
+    ndjson_report: Option<&mut NdjsonReportWriter>,
+) -> Vec<ProjectOutcome> {
+    let mut state = RunState {
+        projects,
+        plain_progress: settings.plain_progress,
+        quiet: settings.quiet,
+        fail_fast: settings.fail_fast,
+        first_failure: None,
+        overall_progress_bar,
+        ndjson_report,
+        outcomes: projects.iter().map(|_| None).collect(),
+        finished: HashSet::new(),
+        unavailable: HashSet::new(),
+        host_slots: HostSlots::new(settings.global_config.max_concurrent_per_host),
+    };
+
+    thread::scope(|scope| {
+        let (event_tx, event_rx) = mpsc::channel::<WorkerEvent>();
+        let mut in_flight = 0;
+        // Not yet started, in config order. A project waiting on a running
+        // dependency or for a slot on its host stays here while later ones
+        // go ahead.
+        let mut pending: Vec<_> = projects.iter().zip(path_checks).enumerate().collect();
+
+        while !pending.is_empty() {
+            let ready = pending.iter().position(|(_, (project_config, _))| {
+                !state.has_pending_dependency(project_config)
+                    && state.host_slots.is_free(project_host(project_config))
+            });
+            // Projects are ordered so dependencies come first; when nothing
+            // is ready, an unfinished dependency or a project holding the
+            // host's slots is running and will report back.
+            let Some(position) = ready.filter(|_| in_flight < settings.jobs) else {
+                match event_rx.recv().expect("a running project reports back") {
+                    WorkerEvent::HostReleased(index) => state.host_slots.release(index),
+                    WorkerEvent::Finished(finished_index, outcome) => {
+                        in_flight -= 1;
+                        state.host_slots.release(finished_index);
+                        state.record(finished_index, outcome);
+                    }
+                }
+                continue;
+            };
+            let (index, (project_config, path_check)) = pending.remove(position);
+
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                let skip_msg = format!(
+                    "Project '{}': Not started because the run was interrupted.",
+                    project_config.project
+                );
+                state.record_skip(index, skip_msg, "interrupted");
+                continue;
+            }
+            if let Some(failed) = state.first_failure {
+                let skip_msg = format!(
+                    "Project '{}': Not started because '{}' failed and --fail-fast is set.",
+                    project_config.project, failed
+                );
+                state.unavailable.insert(&project_config.project);
+                state.record_skip(index, skip_msg, "fail-fast");
+                continue;
+            }
+            if let Some(dependency) = state.failed_dependency(project_config) {
+                let skip_msg = format!(
+                    "Project '{}': Skipped because dependency '{}' did not complete.",
+                    project_config.project, dependency
+                );
+                state.unavailable.insert(&project_config.project);
+                state.record_skip(
+                    index,
+                    skip_msg,
+                    &format!("dependency '{}' failed", dependency),
+                );
+                continue;
+            }
+
+            if let Some(max_load) = settings.max_load {
+                wait_for_load_below(max_load, overall_progress_bar);
+            }
+            state.show_progress(
+                index,
+                LogLevel::Info,
+                format!("Starting: {}", project_config.project),
+            );
+            in_flight += 1;
+            state.host_slots.take(index, project_host(project_config));
+            let event_tx = event_tx.clone();
+            scope.spawn(move || {
+                let progress_bar =
+                    multi_progress.insert_before(overall_progress_bar, ProgressBar::new_spinner());
+                progress_bar.set_style(
+                    ProgressStyle::default_spinner()
+                        .template("  {spinner:.green} {wide_msg}")
+                        .expect("Failed to set progress bar template"),
+                );
+                progress_bar.enable_steady_tick(SPINNER_TICK);
+                // The receiver outlives every worker inside this scope.
+                let release_host = || {
+                    let _ = event_tx.send(WorkerEvent::HostReleased(index));
+                };
+                let outcome = run_project(
+                    project_config,
+                    path_check,
+                    settings,
+                    multi_progress,
+                    &progress_bar,
+                    &release_host,
+                );
+                progress_bar.finish_and_clear();
+                multi_progress.remove(&progress_bar);
+                let _ = event_tx.send(WorkerEvent::Finished(index, outcome));
+            });
+        }
+
+        drop(event_tx);
+        for event in event_rx {
+            if let WorkerEvent::Finished(finished_index, outcome) = event {
+                state.record(finished_index, outcome);
+            }
+        }
+    });
+
+    state
+        .outcomes
+        .into_iter()
+        .map(|outcome| outcome.expect("every project has an outcome"))
+        .collect()
+}
+
+/// Runs a single project on a worker thread. A panic is reported as a failed
+/// project rather than taking down the whole run.
+fn run_project(
+    project_config: &ProjectConfig,
+    path_check: Result<PathBuf, ProjectError>,
+    settings: &RunSettings,
+    multi_progress: &MultiProgress,
+    progress_bar: &ProgressBar,
+    release_host: &dyn Fn(),
+) -> ProjectOutcome {
+    let _project_log = enter_project_log(&project_config.project);
+    let project_started = Instant::now();
+    let project_path = path_check.as_ref().ok().cloned();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        path_check.and_then(|_| {
+            process_project(
+                project_config,
+                settings.global_config,
+                settings.parent_clone_dir,
+                progress_bar,
+                multi_progress,
+                release_host,
+            )
+        })
+    }));
+    let mut outcome = match result {
+        Ok(Ok(outcome)) => outcome.finish(project_started.elapsed()),
+        failure => {
+            let error_message = match failure {
+                Ok(Err(e)) => e.to_string(),
+                _ => format!("Project '{}': Processing panicked.", project_config.project),
+            };
+            log_to_file(
+                LogLevel::Error,
+                &format!(
+                    "Error processing project {}: {}",
+                    project_config.project, error_message
+                ),
+            );
+            ProjectOutcome::failed(
+                &project_config.project,
+                error_message,
+                project_started.elapsed(),
+            )
+        }
+    };
+    // Also covers projects that failed or were skipped before getting there.
+    release_host();
+
+    if settings.post_status {
+        if let Some(project_path) = &project_path {
+            outcome.post_status = collect_post_status(
+                project_config,
+                settings.global_config,
+                project_path,
+                progress_bar,
+            );
+        }
+    }
+    outcome
+}
+
+/// Host a project's remote counts against for `max_concurrent_per_host`;
+/// `None` for local paths.
+fn project_host(project_config: &ProjectConfig) -> Option<&str> {
+    url_host_and_repo_path(&project_config.url)
+        .map(|(host, _)| host)
+        .filter(|host| !host.is_empty())
+}
+
+/// Bookkeeping done on the coordinating thread as projects finish.
+struct RunState<'a, 'w> {
+    projects: &'a [ProjectConfig],
+    plain_progress: bool,
+    quiet: bool,
+    fail_fast: bool,
+    /// The first project that failed, once `fail_fast` has stopped the run.
+    first_failure: Option<&'a str>,
+    overall_progress_bar: &'a ProgressBar,
+    ndjson_report: Option<&'w mut NdjsonReportWriter>,
+    outcomes: Vec<Option<ProjectOutcome>>,
+    finished: HashSet<&'a str>,
+    /// Projects that failed, or were skipped because a dependency failed.
+    unavailable: HashSet<&'a str>,
+    host_slots: HostSlots<'a>,
+}
+
+impl<'a> RunState<'a, '_> {
+    fn has_pending_dependency(&self, project_config: &ProjectConfig) -> bool {
+        project_config
+            .depends_on
+            .iter()
+            .flatten()
+            .any(|dependency| !self.finished.contains(dependency.as_str()))
+    }
+
+    fn failed_dependency<'p>(&self, project_config: &'p ProjectConfig) -> Option<&'p String> {
+        project_config
+            .depends_on
+            .iter()
+            .flatten()
+            .find(|dependency| self.unavailable.contains(dependency.as_str()))
+    }
+
+    fn record_skip(&mut self, index: usize, skip_msg: String, reason: &str) {
+        log_to_file(LogLevel::Warning, &skip_msg);
+        let project = &self.projects[index].project;
+        self.show_progress(
+            index,
+            LogLevel::Warning,
+            format!("Skipped: {} ({})", project, reason),
+        );
+        let mut outcome = ProjectOutcome::new(project);
+        outcome.skipped = Some(skip_msg);
+        self.store(index, outcome.finish(Duration::ZERO));
+    }
+
+    fn record(&mut self, index: usize, outcome: ProjectOutcome) {
+        let project = &self.projects[index].project;
+        let (level, message) = if outcome.status == ProjectStatus::Error {
+            self.unavailable.insert(project);
+            if self.fail_fast && self.first_failure.is_none() {
+                self.first_failure = Some(project);
+            }
+            (LogLevel::Error, format!("Error: {} (see log)", project))
+        } else {
+            (LogLevel::Info, format!("Done: {}", project))
+        };
+        self.show_progress(index, level, message);
+        self.store(index, outcome);
+    }
+
+    fn store(&mut self, index: usize, outcome: ProjectOutcome) {
+        if let Some(writer) = self.ndjson_report.as_mut() {
+            if let Err(e) = writer.write(&outcome) {
+                log_to_file(
+                    LogLevel::Error,
+                    &format!(
+                        "Failed to append '{}' to the NDJSON report: {}",
+                        outcome.project, e
+                    ),
+                );
+            }
+        }
+        self.finished.insert(&self.projects[index].project);
+        self.outcomes[index] = Some(outcome);
+        self.overall_progress_bar.inc(1);
+    }
+
+    /// Updates the overall bar message, or prints it as a line numbered by the
+    /// project's position in the run in plain mode. Errors and skips are also
+    /// printed above the bar, colored, so they stay visible; `--quiet` keeps
+    /// only the errors.
+    fn show_progress(&self, index: usize, level: LogLevel, message: String) {
+        let line = format!("[{}/{}] {}", index + 1, self.projects.len(), message);
+        match level {
+            LogLevel::Error => self
+                .overall_progress_bar
+                .suspend(|| print_level_status(level, &line)),
+            _ if self.quiet => {}
+            LogLevel::Warning => self
+                .overall_progress_bar
+                .suspend(|| print_level_status(level, &line)),
+            _ if self.plain_progress => print_status(&line),
+            _ => {}
+        }
+        self.overall_progress_bar.set_message(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::set_log_file;
+    use crate::report::RunReport;
+    use indicatif::ProgressDrawTarget;
+
+    #[test]
+    fn parallel_run_reports_every_project_once_in_config_order() {
+        let log_path =
+            std::env::temp_dir().join(format!("git-project-updater-{}.log", std::process::id()));
+        set_log_file(&log_path.to_string_lossy()).expect("temp log file");
+
+        // Every fifth project depends on the one before it, which fails, so
+        // skips and failures finish in a different order than they started.
+        let projects: Vec<ProjectConfig> = (0..40)
+            .map(|i| {
+                let depends_on = (i % 5 == 4).then(|| vec![format!("p{}", i - 1)]);
+                serde_json::from_value(serde_json::json!({
+                    "project": format!("p{}", i),
+                    "url": format!("https://example.com/p{}.git", i),
+                    "path": format!("p{}", i),
+                    "depends_on": depends_on,
+                }))
+                .expect("minimal project config")
+            })
+            .collect();
+        // Rejected paths fail each project without running git.
+        let path_checks = projects
+            .iter()
+            .map(|project| {
+                Err(ProjectError::UnsafePath {
+                    project_name: project.project.clone(),
+                    path: PathBuf::from(&project.path),
+                    reason: "test".to_string(),
+                })
+            })
+            .collect();
+        let global_config = GlobalConfig::default();
+        let settings = RunSettings {
+            global_config: &global_config,
+            parent_clone_dir: Path::new("/nonexistent"),
+            jobs: 8,
+            max_load: None,
+            post_status: false,
+            plain_progress: false,
+            quiet: true,
+            fail_fast: false,
+        };
+        let multi_progress = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        let overall_progress_bar = multi_progress.add(ProgressBar::new(projects.len() as u64));
+
+        let outcomes = run_projects(
+            &projects,
+            path_checks,
+            &settings,
+            &multi_progress,
+            &overall_progress_bar,
+            None,
+        );
+        let _ = std::fs::remove_file(&log_path);
+
+        let report = serde_json::to_value(RunReport::new(&outcomes, Duration::ZERO))
+            .expect("report serializes");
+        let reported: Vec<&str> = report["projects"]
+            .as_array()
+            .expect("projects array")
+            .iter()
+            .map(|project| project["project"].as_str().expect("project name"))
+            .collect();
+        let expected: Vec<String> = (0..40).map(|i| format!("p{}", i)).collect();
+        assert_eq!(reported, expected);
+        assert_eq!(report["counts"]["total"], 40);
+        assert_eq!(report["counts"]["error"], 32);
+        assert_eq!(report["counts"]["skipped"], 8);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::display::print_level_status;
+use crate::logger::{log_to_file, LogLevel};
+use indicatif::ProgressBar;
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+
+const LOAD_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// File descriptors set aside per running project: git's pipes, the
+/// processes it spawns (ssh, remote helpers) and the files they open.
+const FDS_PER_JOB: u64 = 32;
+/// Projects per CPU beyond which more `--jobs` only add contention.
+const JOBS_PER_CPU: usize = 4;
+
+/// Which running projects hold a slot on their host, for
+/// `max_concurrent_per_host`. Kept by the coordinating thread, which only
+/// starts a project whose host has a free slot, so a project waiting for its
+/// host never occupies one of the `--jobs` workers.
+pub struct HostSlots<'a> {
+    limit: Option<usize>,
+    /// Host of each project, by index, from start until it releases its slot.
+    held: BTreeMap<usize, &'a str>,
+}
+
+impl<'a> HostSlots<'a> {
+    /// `limit` of `None` never holds anyone back.
+    pub fn new(limit: Option<usize>) -> Self {
+        HostSlots {
+            limit,
+            held: BTreeMap::new(),
+        }
+    }
+
+    /// Whether a project on `host` can start now. Remotes without a host
+    /// (local paths) are never limited.
+    pub fn is_free(&self, host: Option<&str>) -> bool {
+        match (self.limit, host) {
+            (Some(limit), Some(host)) => {
+                self.held.values().filter(|held| **held == host).count() < limit
+            }
+            _ => true,
+        }
+    }
+
+    /// Counts project `index` against `host` until [`HostSlots::release`].
+    pub fn take(&mut self, index: usize, host: Option<&'a str>) {
+        if let Some(host) = host.filter(|_| self.limit.is_some()) {
+            self.held.insert(index, host);
+        }
+    }
+
+    /// Frees the slot of project `index`, if it still holds one.
+    pub fn release(&mut self, index: usize) {
+        self.held.remove(&index);
+    }
+}
+
+/// Highest `--jobs` this machine is expected to cope with: a few projects
+/// per CPU, and no more than the open file limit leaves room for.
+pub fn soft_jobs_cap() -> usize {
+    let cpus = thread::available_parallelism().map_or(1, |cpus| cpus.get());
+    let cpu_cap = cpus.saturating_mul(JOBS_PER_CPU);
+    match open_file_limit() {
+        Some(limit) => cpu_cap.min(
+            usize::try_from(limit / FDS_PER_JOB)
+                .unwrap_or(usize::MAX)
+                .max(1),
+        ),
+        None => cpu_cap,
+    }
+}
+
+/// Lowers `requested` workers to `soft_cap` with a warning, or only warns
+/// with `--force-jobs`.
+pub fn cap_jobs(requested: usize, soft_cap: usize, force: bool) -> usize {
+    if requested <= soft_cap {
+        return requested;
+    }
+    let message = if force {
+        format!(
+            "--jobs {} exceeds the soft cap of {} for this machine; running anyway because of --force-jobs.",
+            requested, soft_cap
+        )
+    } else {
+        format!(
+            "--jobs {} exceeds the soft cap of {} for this machine and could exhaust file descriptors or memory; using {} (pass --force-jobs to override).",
+            requested, soft_cap, soft_cap
+        )
+    };
+    log_to_file(LogLevel::Warning, &message);
+    print_level_status(LogLevel::Warning, &message);
+    if force {
+        requested
+    } else {
+        soft_cap
+    }
+}
+
+/// Blocks until the 1-minute load average drops to `max_load` or below, so a
+/// new project is not started on an already busy machine. Does nothing when
+/// the load average cannot be read on this platform.
+pub fn wait_for_load_below(max_load: f64, progress_bar: &ProgressBar) {
+    let Some(mut load) = current_load_average() else {
+        return;
+    };
+    if load <= max_load {
+        return;
+    }
+
+    log_to_file(
+        LogLevel::Info,
+        &format!(
+            "System load {:.2} exceeds --max-load {:.2}; pausing before starting the next project.",
+            load, max_load
+        ),
+    );
+    while load > max_load {
+        progress_bar.set_message(format!(
+            "Waiting for system load {:.2} to drop below {:.2}...",
+            load, max_load
+        ));
+        thread::sleep(LOAD_POLL_INTERVAL);
+        match current_load_average() {
+            Some(current) => load = current,
+            None => return,
+        }
+    }
+    log_to_file(
+        LogLevel::Info,
+        &format!("System load back to {:.2}; resuming.", load),
+    );
+}
+
+/// Warns once up front when `--max-load` cannot be honoured on this platform.
+pub fn check_load_support() {
+    if current_load_average().is_none() {
+        log_to_file(
+            LogLevel::Warning,
+            "--max-load is not supported on this platform; load-based throttling is disabled.",
+        );
+    }
+}
+
+#[cfg(unix)]
+fn current_load_average() -> Option<f64> {
+    let mut loads = [0f64; 3];
+    // SAFETY: `loads` is a valid buffer for the three samples requested.
+    let samples = unsafe { libc::getloadavg(loads.as_mut_ptr(), 1) };
+    (samples >= 1).then_some(loads[0])
+}
+
+#[cfg(not(unix))]
+fn current_load_average() -> Option<f64> {
+    None
+}
+
+/// Soft `RLIMIT_NOFILE` of this process; `None` when unlimited or unknown.
+#[cfg(unix)]
+fn open_file_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid, writable `rlimit`.
+    let result = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+    (result == 0 && limit.rlim_cur != libc::RLIM_INFINITY).then_some(limit.rlim_cur)
+}
+
+#[cfg(not(unix))]
+fn open_file_limit() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_within_soft_cap_are_kept() {
+        assert_eq!(cap_jobs(8, 16, false), 8);
+        assert_eq!(cap_jobs(16, 16, false), 16);
+    }
+
+    #[test]
+    fn jobs_above_soft_cap_are_lowered_unless_forced() {
+        assert_eq!(cap_jobs(500, 16, false), 16);
+        assert_eq!(cap_jobs(500, 16, true), 500);
+    }
+
+    #[test]
+    fn soft_jobs_cap_allows_at_least_one_job() {
+        assert!(soft_jobs_cap() >= 1);
+    }
+
+    #[test]
+    fn host_slots_limit_each_host_separately() {
+        let mut slots = HostSlots::new(Some(1));
+        slots.take(0, Some("github.com"));
+        assert!(!slots.is_free(Some("github.com")));
+        assert!(slots.is_free(Some("gitlab.com")));
+        assert!(slots.is_free(None));
+
+        slots.release(0);
+        assert!(slots.is_free(Some("github.com")));
+        // Released again when the project finishes.
+        slots.release(0);
+        assert!(slots.is_free(Some("github.com")));
+    }
+
+    #[test]
+    fn host_slots_without_limit_never_block() {
+        let mut slots = HostSlots::new(None);
+        slots.take(0, Some("github.com"));
+        slots.take(1, Some("github.com"));
+        assert!(slots.is_free(Some("github.com")));
+    }
+}
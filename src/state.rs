@@ -0,0 +1,75 @@
+use crate::logger::{log_to_file, LogLevel};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// File next to the config that remembers when each project last synced.
+const STATE_FILE_NAME: &str = ".git-fetcher-state.json";
+
+/// Per-project bookkeeping kept between runs, used by `--since`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SyncState {
+    /// Seconds since the Unix epoch of each project's last successful sync.
+    #[serde(default)]
+    last_synced: BTreeMap<String, u64>,
+}
+
+impl SyncState {
+    /// Reads the state file. A missing file is an empty state; an unreadable
+    /// one is logged and treated the same, so every project is due.
+    pub fn load(path: &Path) -> SyncState {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return SyncState::default(),
+            Err(e) => {
+                log_to_file(
+                    LogLevel::Warning,
+                    &format!(
+                        "Cannot read state file '{}': {}; treating every project as never synced.",
+                        path.display(),
+                        e
+                    ),
+                );
+                return SyncState::default();
+            }
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            log_to_file(
+                LogLevel::Warning,
+                &format!("Ignoring malformed state file '{}': {}", path.display(), e),
+            );
+            SyncState::default()
+        })
+    }
+
+    /// How long ago `project` last synced, `None` if it never did.
+    pub fn synced_ago(&self, project: &str, now: SystemTime) -> Option<Duration> {
+        let synced_at = UNIX_EPOCH + Duration::from_secs(*self.last_synced.get(project)?);
+        Some(now.duration_since(synced_at).unwrap_or_default())
+    }
+
+    pub fn record_sync(&mut self, project: &str, at: SystemTime) {
+        let secs = at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        self.last_synced.insert(project.to_string(), secs);
+    }
+
+    /// Writes the state through a temporary file, so an interrupted write
+    /// never leaves a truncated state behind.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        fs::write(&temp_path, serde_json::to_string_pretty(self)? + "\n")?;
+        fs::rename(&temp_path, path)
+    }
+}
+
+/// `.git-fetcher-state.json` in the directory of `config_file_path`.
+pub fn state_file_path(config_file_path: &Path) -> PathBuf {
+    config_file_path.with_file_name(STATE_FILE_NAME)
+}